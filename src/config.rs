@@ -1,33 +1,41 @@
-// Grid Configuration
-pub const GRID_WIDTH: usize = 250;
-pub const GRID_HEIGHT: usize = 250;
-pub const CELL_SIZE: f32 = 4.0;
+// Grid Configuration (defaults, see `Settings`)
+pub const DEFAULT_GRID_WIDTH: usize = 250;
+pub const DEFAULT_GRID_HEIGHT: usize = 250;
+pub const DEFAULT_CELL_SIZE: f32 = 4.0;
+pub const MIN_GRID_SIZE: usize = 16;
+pub const MAX_GRID_SIZE: usize = 2048;
+pub const SETTINGS_PATH: &str = "settings.ron"; // Optional, the defaults apply without it
+pub const CHUNK_SIZE: usize = 25; // Cells per side of a simulation chunk
+
+// Simulation Configuration
+pub const SIMULATION_SEED: Option<u64> = None; // Fixed seed for reproducible runs, random when None
+pub const DEFAULT_TICKS_PER_SECOND: f32 = 60.0; // Simulation rate, independent of the frame rate (see `Settings`)
+pub const MIN_TICKS_PER_SECOND: f32 = 1.0;
+pub const MAX_TICKS_PER_SECOND: f32 = 480.0;
+pub const MAX_TICKS_PER_FRAME: u32 = 16; // Catch-up cap: past it the simulation slows down instead of stalling frames
+
+// Material Configuration
+pub const MATERIALS_PATH: &str = "assets/materials.ron";
+
+// Save Configuration
+pub const DEFAULT_SAVE_PATH: &str = "saves/grid.sav";
+
+// Image Configuration
+pub const PALETTE_PATH: &str = "assets/palette.ron"; // Optional, material colours are used without it
+pub const DEFAULT_IMPORT_PATH: &str = "levels/level.png";
+pub const DEFAULT_EXPORT_PATH: &str = "screenshots/grid.png";
+pub const DEFAULT_RECORDING_PATH: &str = "recordings/clip.gif";
+pub const DEFAULT_RECORDING_SECONDS: u32 = 10;
+
+// Editing Configuration
+pub const DEFAULT_STAMP_PATH: &str = "assets/stamp.png"; // Bitmap used by the stamp brush shape
+pub const UNDO_MEMORY_BUDGET: usize = 32 * 1024 * 1024; // Bytes of stroke history kept for undo and redo
 
 // UI Configuration
 pub const UI_SIDEBAR_WIDTH: f32 = 230.0;
 pub const UI_PANEL_DEFAULT_WIDTH: f32 = 100.0;
 
 // Camera Configuration
-// This offset compensates for the UI sidebar to ensure proper alignment
-// between mouse position and grid coordinates
-pub const CAMERA_OFFSET_X: f32 = UI_SIDEBAR_WIDTH / 2.0;
-
-// Window Configuration
-pub struct WindowConfig {
-    pub width: f32,
-    pub height: f32,
-}
-
-impl WindowConfig {
-    pub fn new() -> Self {
-        // Calculate window dimensions based on grid and UI sizes
-        let width = (GRID_WIDTH as f32 * CELL_SIZE) + UI_SIDEBAR_WIDTH;
-        let height = GRID_HEIGHT as f32 * CELL_SIZE;
-        
-        Self { width, height }
-    }
-}
-
-// Derived Constants
-pub const GRID_PIXEL_WIDTH: f32 = GRID_WIDTH as f32 * CELL_SIZE;
-pub const GRID_PIXEL_HEIGHT: f32 = GRID_HEIGHT as f32 * CELL_SIZE;
+pub const CAMERA_MIN_SCALE: f32 = 1.0 / 32.0; // World pixels per screen pixel when fully zoomed in
+pub const CAMERA_MAX_SCALE: f32 = 16.0; // World pixels per screen pixel when fully zoomed out
+pub const CAMERA_ZOOM_STEP: f32 = 1.1; // Zoom factor per line of mouse wheel scrolling
@@ -1,75 +1,661 @@
+use std::marker::PhantomData;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use bevy::prelude::*;
-use crate::config::{GRID_WIDTH, GRID_HEIGHT};
+use crate::config::CHUNK_SIZE;
 use crate::materials::Material;
-use crate::materials::types::Particle;
+use crate::materials::explosion::Explosion;
+use crate::materials::thermal::{HeatScratch, AMBIENT_TEMPERATURE};
+use crate::materials::types::{Particle, ParticleFlags};
+use crate::registry::MaterialRegistry;
+use crate::utils::rng::cell_noise;
 
-/// The simulation grid, storing materials in a 1D vector
-#[derive(Clone, Resource)]
+static NEXT_GRID_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Everything stored for a single cell
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cell {
+    pub particle: Particle,
+    pub velocity: Vec2,
+    pub temperature: f32,
+    pub fuel: u8,
+}
+
+/// The simulation grid, storing materials in a 1D vector, row by row.
+/// The grid is split into square chunks of `CHUNK_SIZE` cells that are only
+/// simulated while something in or next to them changed on the previous tick.
+#[derive(Resource)]
 pub struct Grid {
     pub particles: Vec<Particle>,
-    pub velocities: Vec<f32>,
+    pub velocities: Vec<Vec2>,
+    pub temperatures: Vec<f32>,
+    pub fuel: Vec<u8>,
+    moved_at: Vec<u32>, // Tick each cell's particle last moved on
+    tick: u32, // Ticks begun so far, 0 before the first so no cell counts as moved
+    registry: MaterialRegistry,
+    width: usize,
+    height: usize,
+    chunks_x: usize,
+    chunks_y: usize,
+    id: u64,
+    // Shared with concurrently updated chunks, see `update_grid`
+    explosions: Mutex<Vec<Explosion>>,
+    changed_chunks: Vec<AtomicBool>, // Chunks written to since the current tick started
+    active_chunks: Vec<bool>,  // Chunks simulated during the current tick
+    dirty_chunks: Vec<AtomicBool>, // Chunks whose colours changed since they were last drawn
+    written_chunks: Vec<AtomicBool>, // Chunks whose cells changed since the last `copy_changes_from`
+    pub(crate) warm_chunks: Vec<bool>, // Chunks whose temperatures were still settling when heat was last conducted
+    pub(crate) heat_scratch: HeatScratch,
 }
 
 impl Grid {
-    /// Creates a new grid, initialized with empty cells (0)
-    pub fn new() -> Self {
+    /// Creates a new `width` x `height` grid, initialized with empty cells
+    pub fn new(registry: MaterialRegistry, width: usize, height: usize) -> Self {
+        let (chunks_x, chunks_y) = (width.div_ceil(CHUNK_SIZE), height.div_ceil(CHUNK_SIZE));
         Grid {
-            particles: vec![Particle::new(Material::Empty); GRID_WIDTH * GRID_HEIGHT],
-            velocities: vec![0.0; GRID_WIDTH * GRID_HEIGHT],
+            particles: vec![registry.particle(registry.empty()); width * height],
+            velocities: vec![Vec2::ZERO; width * height],
+            temperatures: vec![AMBIENT_TEMPERATURE; width * height],
+            fuel: vec![0; width * height],
+            moved_at: vec![0; width * height],
+            tick: 0,
+            registry,
+            width,
+            height,
+            chunks_x,
+            chunks_y,
+            id: NEXT_GRID_ID.fetch_add(1, Ordering::Relaxed),
+            explosions: Mutex::new(Vec::new()),
+            changed_chunks: (0..chunks_x * chunks_y).map(|_| AtomicBool::new(false)).collect(),
+            active_chunks: vec![false; chunks_x * chunks_y],
+            // Nothing was drawn yet
+            dirty_chunks: (0..chunks_x * chunks_y).map(|_| AtomicBool::new(true)).collect(),
+            written_chunks: (0..chunks_x * chunks_y).map(|_| AtomicBool::new(false)).collect(),
+            warm_chunks: vec![false; chunks_x * chunks_y],
+            heat_scratch: HeatScratch::default(),
         }
     }
 
+    /// A copy of this grid at a new size, with the contents anchored to the
+    /// bottom and centred horizontally. Whatever falls outside is cut off.
+    pub fn resized(&self, width: usize, height: usize) -> Grid {
+        let mut grid = Grid::new(self.registry.clone(), width, height);
+        let offset_x = (width as isize - self.width as isize) / 2;
+        let offset_y = height as isize - self.height as isize;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let (nx, ny) = (x as isize + offset_x, y as isize + offset_y);
+                if grid.in_bounds(nx, ny) {
+                    let (from, to) = (y * self.width + x, ny as usize * width + nx as usize);
+                    grid.particles[to] = self.particles[from];
+                    grid.velocities[to] = self.velocities[from];
+                    grid.temperatures[to] = self.temperatures[from];
+                    grid.fuel[to] = self.fuel[from];
+                }
+            }
+        }
+
+        grid.wake_all();
+        grid
+    }
+
+    /// Width in cells
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    /// Height in cells
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Number of chunk columns
+    pub fn chunks_x(&self) -> usize {
+        self.chunks_x
+    }
+
+    /// Number of chunk rows
+    pub fn chunks_y(&self) -> usize {
+        self.chunks_y
+    }
+
+    /// The material registry used to create new particles
+    pub fn registry(&self) -> &MaterialRegistry {
+        &self.registry
+    }
+
+    /// Identifies this grid's contents across ticks; a newly created, loaded or imported grid gets a new id
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
     /// Gets the material at (x, y)
     pub fn get(&self, x: usize, y: usize) -> Particle {
-        self.particles[y * GRID_WIDTH + x]
+        self.particles[y * self.width + x]
     }
 
-    /// Sets the material at (x, y)
+    /// Whether the cell at (x, y) holds nothing
+    pub fn is_empty(&self, x: usize, y: usize) -> bool {
+        self.get(x, y).material() == self.registry.empty()
+    }
+
+    /// The colour the cell at (x, y) is drawn with
+    pub fn color(&self, x: usize, y: usize) -> (u8, u8, u8) {
+        let particle = self.get(x, y);
+        if particle.flags.contains(ParticleFlags::BURNING) {
+            self.registry.properties(self.registry.fire()).color
+        } else {
+            particle.get_color()
+        }
+    }
+
+    /// Sets the material at (x, y), as a new particle with its own shade of the material's colour
     pub fn set(&mut self, x: usize, y: usize, material: Material) {
-        self.particles[y * GRID_WIDTH + x] = Particle::new(material);
+        self.writer().set(x, y, material);
+    }
+
+    /// Places a fresh particle at (x, y), starting at its material's temperature
+    pub fn spawn(&mut self, x: usize, y: usize, material: Material) {
+        self.writer().spawn(x, y, material);
     }
 
     /// Sets the particle at (x, y)
     pub fn set_particle(&mut self, x: usize, y: usize, particle: Particle) {
-        self.particles[y * GRID_WIDTH + x] = particle;
+        self.writer().set_particle(x, y, particle);
+    }
+
+    /// Gets everything stored for the cell at (x, y)
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        Cell {
+            particle: self.get(x, y),
+            velocity: self.get_velocity(x, y),
+            temperature: self.get_temperature(x, y),
+            fuel: self.get_fuel(x, y),
+        }
+    }
+
+    /// Replaces everything stored for the cell at (x, y)
+    pub fn set_cell(&mut self, x: usize, y: usize, cell: Cell) {
+        self.set_particle(x, y, cell.particle);
+        self.set_velocity(x, y, cell.velocity);
+        self.set_temperature(x, y, cell.temperature);
+        self.set_fuel(x, y, cell.fuel);
+        self.wake(x, y);
     }
 
     /// Checks if (x, y) is within the grid bounds
     pub fn in_bounds(&self, x: isize, y: isize) -> bool {
-        x >= 0 && x < GRID_WIDTH as isize && y >= 0 && y < GRID_HEIGHT as isize
+        x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize
+    }
+
+    pub fn get_velocity(&self, x: usize, y: usize) -> Vec2 {
+        self.velocities[y * self.width + x]
+    }
+
+    pub fn set_velocity(&mut self, x: usize, y: usize, velocity: Vec2) {
+        self.writer().set_velocity(x, y, velocity);
+    }
+
+    /// Moves material from (x1, y1) to (x2, y2) if the target is empty, leaving it moving at `velocity`
+    pub fn move_to_with_velocity(&mut self, x1: usize, y1: usize, x2: usize, y2: usize, velocity: Vec2) {
+        self.writer().move_to_with_velocity(x1, y1, x2, y2, velocity);
+    }
+
+    /// Whether the particle at (x, y) moved into its cell or was swapped there on the latest tick
+    pub fn moved_last_tick(&self, x: usize, y: usize) -> bool {
+        self.tick != 0 && self.moved_at[y * self.width + x] == self.tick
+    }
+
+    pub fn get_fuel(&self, x: usize, y: usize) -> u8 {
+        self.fuel[y * self.width + x]
+    }
+
+    pub fn set_fuel(&mut self, x: usize, y: usize, fuel: u8) {
+        self.writer().set_fuel(x, y, fuel);
+    }
+
+    pub fn get_temperature(&self, x: usize, y: usize) -> f32 {
+        self.temperatures[y * self.width + x]
+    }
+
+    /// Sets the temperature at (x, y) without waking its chunk, as heat alone
+    /// only matters once it triggers a transition
+    pub fn set_temperature(&mut self, x: usize, y: usize, temperature: f32) {
+        self.writer().set_temperature(x, y, temperature);
+    }
+
+    /// Queues an explosion to be set off at the end of the current simulation step
+    pub fn queue_explosion(&mut self, explosion: Explosion) {
+        self.writer().queue_explosion(explosion);
+    }
+
+    /// Takes all explosions queued since the last call
+    pub fn take_explosions(&mut self) -> Vec<Explosion> {
+        std::mem::take(self.explosions.get_mut().unwrap())
+    }
+
+    /// Keeps the chunk holding (x, y) awake for the next tick, for particles
+    /// that are waiting on a random roll rather than visibly changing
+    pub fn wake(&self, x: usize, y: usize) {
+        self.changed_chunks[(y / CHUNK_SIZE) * self.chunks_x + x / CHUNK_SIZE].store(true, Ordering::Relaxed);
+    }
+
+    /// Wakes every chunk, e.g. after the whole grid was replaced
+    pub fn wake_all(&mut self) {
+        for changed in &mut self.changed_chunks {
+            *changed.get_mut() = true;
+        }
+    }
+
+    /// Marks every chunk for redrawing, e.g. once the texture lost its contents
+    pub fn mark_all_dirty(&mut self) {
+        for dirty in &mut self.dirty_chunks {
+            *dirty.get_mut() = true;
+        }
+    }
+
+    /// Takes the regions whose colours changed since the last call, as cell rectangles.
+    /// Dirty chunks next to each other in a row of chunks are merged into one rectangle.
+    pub fn take_dirty_regions(&mut self) -> Vec<URect> {
+        let mut regions = Vec::new();
+        for cy in 0..self.chunks_y {
+            let mut run_start = None;
+            for cx in 0..=self.chunks_x {
+                let dirty = cx < self.chunks_x
+                    && std::mem::take(self.dirty_chunks[cy * self.chunks_x + cx].get_mut());
+                match (dirty, run_start) {
+                    (true, None) => run_start = Some(cx),
+                    (false, Some(start)) => {
+                        let (xs, ys) = self.chunk_bounds(start, cy);
+                        let end = self.chunk_bounds(cx - 1, cy).0.end;
+                        regions.push(URect::new(xs.start as u32, ys.start as u32, end as u32, ys.end as u32));
+                        run_start = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        regions
+    }
+
+    /// Starts a tick: chunks that changed since the last one, and their
+    /// neighbours, become active while every other chunk sleeps
+    pub fn begin_tick(&mut self) {
+        for cy in 0..self.chunks_y {
+            for cx in 0..self.chunks_x {
+                let mut active = false;
+                for ny in cy.saturating_sub(1)..=(cy + 1).min(self.chunks_y - 1) {
+                    for nx in cx.saturating_sub(1)..=(cx + 1).min(self.chunks_x - 1) {
+                        active |= self.changed_chunks[ny * self.chunks_x + nx].load(Ordering::Relaxed);
+                    }
+                }
+                self.active_chunks[cy * self.chunks_x + cx] = active;
+            }
+        }
+        for changed in &mut self.changed_chunks {
+            *changed.get_mut() = false;
+        }
+        // Skips 0 on wrapping around, which stays reserved for no tick at all
+        self.tick = self.tick.wrapping_add(1).max(1);
+    }
+
+    /// Whether the chunk at chunk coordinates (cx, cy) is simulated this tick
+    pub fn is_chunk_active(&self, cx: usize, cy: usize) -> bool {
+        self.active_chunks[cy * self.chunks_x + cx]
     }
 
-    /// Swaps materials between two cells
-    pub fn swap(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
-        let idx1 = y1 * GRID_WIDTH + x1;
-        let idx2 = y2 * GRID_WIDTH + x2;
-        self.particles.swap(idx1, idx2);
+    /// Cell bounds of the chunk at chunk coordinates (cx, cy), as x and y ranges
+    pub fn chunk_bounds(&self, cx: usize, cy: usize) -> (std::ops::Range<usize>, std::ops::Range<usize>) {
+        let x = cx * CHUNK_SIZE..((cx + 1) * CHUNK_SIZE).min(self.width);
+        let y = cy * CHUNK_SIZE..((cy + 1) * CHUNK_SIZE).min(self.height);
+        (x, y)
     }
 
-    /// Moves material from (x1, y1) to (x2, y2) if the target is empty (0)
-    pub fn move_to(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
-        if self.get(x2, y2).material_type == Material::Empty as u8 {
+    /// Turns this grid into a copy of `source`. If it was one already before `source`
+    /// last changed, only the chunks written to since are copied, otherwise all of it.
+    pub fn copy_changes_from(&mut self, source: &mut Grid) {
+        if self.id != source.id {
+            self.clone_from(source);
+        } else {
+            for cy in 0..self.chunks_y {
+                for cx in 0..self.chunks_x {
+                    if !*source.written_chunks[cy * self.chunks_x + cx].get_mut() {
+                        continue;
+                    }
+                    let (xs, ys) = self.chunk_bounds(cx, cy);
+                    for y in ys {
+                        let row = y * self.width + xs.start..y * self.width + xs.end;
+                        self.particles[row.clone()].copy_from_slice(&source.particles[row.clone()]);
+                        self.velocities[row.clone()].copy_from_slice(&source.velocities[row.clone()]);
+                        self.temperatures[row.clone()].copy_from_slice(&source.temperatures[row.clone()]);
+                        self.fuel[row.clone()].copy_from_slice(&source.fuel[row.clone()]);
+                        self.moved_at[row.clone()].copy_from_slice(&source.moved_at[row]);
+                    }
+                }
+            }
+            self.copy_state_from(source);
+        }
+        for written in self.written_chunks.iter_mut().chain(&mut source.written_chunks) {
+            *written.get_mut() = false;
+        }
+    }
+
+    // Copies everything but the cells and the written chunks
+    fn copy_state_from(&mut self, source: &Grid) {
+        self.registry.clone_from(&source.registry);
+        self.width = source.width;
+        self.height = source.height;
+        self.chunks_x = source.chunks_x;
+        self.chunks_y = source.chunks_y;
+        self.id = source.id;
+        self.tick = source.tick;
+        self.explosions.get_mut().unwrap().clone_from(&source.explosions.lock().unwrap());
+        self.changed_chunks.resize_with(source.changed_chunks.len(), || AtomicBool::new(false));
+        for (changed, source) in self.changed_chunks.iter_mut().zip(&source.changed_chunks) {
+            *changed.get_mut() = source.load(Ordering::Relaxed);
+        }
+        self.active_chunks.clone_from(&source.active_chunks);
+        self.dirty_chunks.resize_with(source.dirty_chunks.len(), || AtomicBool::new(false));
+        for (dirty, source) in self.dirty_chunks.iter_mut().zip(&source.dirty_chunks) {
+            *dirty.get_mut() = source.load(Ordering::Relaxed);
+        }
+        self.warm_chunks.clone_from(&source.warm_chunks);
+    }
+
+    /// A handle writing into the cells of this grid through a shared reference
+    pub fn writer(&mut self) -> GridWriter<'_> {
+        GridWriter {
+            particles: self.particles.as_mut_ptr(),
+            velocities: self.velocities.as_mut_ptr(),
+            temperatures: self.temperatures.as_mut_ptr(),
+            fuel: self.fuel.as_mut_ptr(),
+            moved_at: self.moved_at.as_mut_ptr(),
+            tick: self.tick,
+            len: self.particles.len(),
+            width: self.width,
+            height: self.height,
+            chunks_x: self.chunks_x,
+            registry: &self.registry,
+            explosions: &self.explosions,
+            changed_chunks: &self.changed_chunks,
+            dirty_chunks: &self.dirty_chunks,
+            written_chunks: &self.written_chunks,
+            _grid: PhantomData,
+        }
+    }
+}
+
+/// Reads and writes single cells of a grid it borrows exclusively, without ever
+/// handing out a reference into the cell buffers. Every method takes `&self`,
+/// so the chunks of one parallel phase can all write through the same writer.
+///
+/// The writer is neither `Send` nor `Sync`. Sharing it between threads is only
+/// sound while no two threads touch the same cell at once; the chunk flags and
+/// the explosion queue are synchronized and may be shared freely.
+pub struct GridWriter<'a> {
+    particles: *mut Particle,
+    velocities: *mut Vec2,
+    temperatures: *mut f32,
+    fuel: *mut u8,
+    moved_at: *mut u32,
+    tick: u32,
+    len: usize, // Cells in each buffer
+    width: usize,
+    height: usize,
+    chunks_x: usize,
+    registry: &'a MaterialRegistry,
+    explosions: &'a Mutex<Vec<Explosion>>,
+    changed_chunks: &'a [AtomicBool],
+    dirty_chunks: &'a [AtomicBool],
+    written_chunks: &'a [AtomicBool],
+    _grid: PhantomData<&'a mut Grid>,
+}
+
+// Each method behaves like the `Grid` method of the same name
+impl GridWriter<'_> {
+    pub fn in_bounds(&self, x: isize, y: isize) -> bool {
+        x >= 0 && x < self.width as isize && y >= 0 && y < self.height as isize
+    }
+
+    // Bounds checked like indexing the buffers directly
+    fn index(&self, x: usize, y: usize) -> usize {
+        let idx = y * self.width + x;
+        assert!(idx < self.len, "cell ({x}, {y}) is outside the grid");
+        idx
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Particle {
+        // SAFETY: the index is in bounds, the writer borrows the grid exclusively and
+        // whoever shares it between threads keeps them off each other's cells
+        unsafe { *self.particles.add(self.index(x, y)) }
+    }
+
+    pub fn is_empty(&self, x: usize, y: usize) -> bool {
+        self.get(x, y).material() == self.registry.empty()
+    }
+
+    pub fn set(&self, x: usize, y: usize, material: Material) {
+        // Salted by the tick, so particles spawned into the same cell one after another differ
+        let salt = (u64::from(self.tick) << 8) | u64::from(material.id());
+        let (r, g, b) = self.registry.properties(material).shade(cell_noise(x, y, salt));
+        self.set_particle(x, y, self.registry.particle(material).with_color(r, g, b));
+    }
+
+    pub fn spawn(&self, x: usize, y: usize, material: Material) {
+        let temperature = self.registry.properties(material).temperature;
+        self.set(x, y, material);
+        self.set_temperature(x, y, temperature);
+    }
+
+    pub fn set_particle(&self, x: usize, y: usize, particle: Particle) {
+        let idx = self.index(x, y);
+        // SAFETY: as in `get`
+        unsafe {
+            if *self.particles.add(idx) != particle {
+                *self.particles.add(idx) = particle;
+                self.wake(x, y);
+                self.mark_dirty(x, y);
+                self.mark_written(x, y);
+            }
+        }
+    }
+
+    /// Swaps materials between two cells, carrying their velocity, heat and fuel along.
+    /// Both cells count as moved on this tick.
+    pub fn swap(&self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        let (idx1, idx2) = (self.index(x1, y1), self.index(x2, y2));
+        // SAFETY: as in `get`; `ptr::swap` allows both cells to be the same
+        unsafe {
+            std::ptr::swap(self.particles.add(idx1), self.particles.add(idx2));
+            std::ptr::swap(self.velocities.add(idx1), self.velocities.add(idx2));
+            std::ptr::swap(self.temperatures.add(idx1), self.temperatures.add(idx2));
+            std::ptr::swap(self.fuel.add(idx1), self.fuel.add(idx2));
+            *self.moved_at.add(idx1) = self.tick;
+            *self.moved_at.add(idx2) = self.tick;
+        }
+        self.wake(x1, y1);
+        self.wake(x2, y2);
+        self.mark_dirty(x1, y1);
+        self.mark_dirty(x2, y2);
+        self.mark_written(x1, y1);
+        self.mark_written(x2, y2);
+    }
+
+    /// Moves material from (x1, y1) to (x2, y2) if the target is empty, leaving the vacated cell at rest
+    pub fn move_to(&self, x1: usize, y1: usize, x2: usize, y2: usize) {
+        if self.is_empty(x2, y2) {
             self.swap(x1, y1, x2, y2);
+            self.set_velocity(x1, y1, Vec2::ZERO);
         }
     }
 
-    pub fn get_velocity(&self, x: usize, y: usize) -> f32 {
-        self.velocities[y * GRID_WIDTH + x]
+    pub fn move_to_with_velocity(&self, x1: usize, y1: usize, x2: usize, y2: usize, velocity: Vec2) {
+        if self.is_empty(x2, y2) {
+            self.swap(x1, y1, x2, y2);
+
+            self.set_velocity(x2, y2, velocity);
+            self.set_velocity(x1, y1, Vec2::ZERO);
+        }
+    }
+
+    pub fn set_velocity(&self, x: usize, y: usize, velocity: Vec2) {
+        let idx = self.index(x, y);
+        // SAFETY: as in `get`
+        unsafe {
+            if *self.velocities.add(idx) != velocity {
+                *self.velocities.add(idx) = velocity;
+                self.wake(x, y);
+                self.mark_written(x, y);
+            }
+        }
+    }
+
+    pub fn set_fuel(&self, x: usize, y: usize, fuel: u8) {
+        let idx = self.index(x, y);
+        // SAFETY: as in `get`
+        unsafe {
+            if *self.fuel.add(idx) != fuel {
+                *self.fuel.add(idx) = fuel;
+                self.wake(x, y);
+                self.mark_written(x, y);
+            }
+        }
+    }
+
+    pub fn get_temperature(&self, x: usize, y: usize) -> f32 {
+        // SAFETY: as in `get`
+        unsafe { *self.temperatures.add(self.index(x, y)) }
+    }
+
+    pub fn set_temperature(&self, x: usize, y: usize, temperature: f32) {
+        let idx = self.index(x, y);
+        // SAFETY: as in `get`
+        unsafe {
+            if *self.temperatures.add(idx) != temperature {
+                *self.temperatures.add(idx) = temperature;
+                self.mark_written(x, y);
+            }
+        }
+    }
+
+    pub fn queue_explosion(&self, explosion: Explosion) {
+        self.wake(explosion.x, explosion.y);
+        self.explosions.lock().unwrap().push(explosion);
+    }
+
+    pub fn wake(&self, x: usize, y: usize) {
+        self.changed_chunks[(y / CHUNK_SIZE) * self.chunks_x + x / CHUNK_SIZE].store(true, Ordering::Relaxed);
+    }
+
+    // Marks the chunk holding (x, y) for redrawing, as the cell looks different now
+    fn mark_dirty(&self, x: usize, y: usize) {
+        self.dirty_chunks[(y / CHUNK_SIZE) * self.chunks_x + x / CHUNK_SIZE].store(true, Ordering::Relaxed);
+    }
+
+    // Records that the chunk holding (x, y) no longer matches the grid's scratch copy
+    fn mark_written(&self, x: usize, y: usize) {
+        self.written_chunks[(y / CHUNK_SIZE) * self.chunks_x + x / CHUNK_SIZE].store(true, Ordering::Relaxed);
     }
+}
+
+impl Clone for Grid {
+    fn clone(&self) -> Self {
+        Grid {
+            particles: self.particles.clone(),
+            velocities: self.velocities.clone(),
+            temperatures: self.temperatures.clone(),
+            fuel: self.fuel.clone(),
+            moved_at: self.moved_at.clone(),
+            tick: self.tick,
+            registry: self.registry.clone(),
+            width: self.width,
+            height: self.height,
+            chunks_x: self.chunks_x,
+            chunks_y: self.chunks_y,
+            id: self.id,
+            explosions: Mutex::new(self.explosions.lock().unwrap().clone()),
+            changed_chunks: self.changed_chunks
+                .iter()
+                .map(|changed| AtomicBool::new(changed.load(Ordering::Relaxed)))
+                .collect(),
+            active_chunks: self.active_chunks.clone(),
+            dirty_chunks: self.dirty_chunks
+                .iter()
+                .map(|dirty| AtomicBool::new(dirty.load(Ordering::Relaxed)))
+                .collect(),
+            written_chunks: self.written_chunks
+                .iter()
+                .map(|written| AtomicBool::new(written.load(Ordering::Relaxed)))
+                .collect(),
+            warm_chunks: self.warm_chunks.clone(),
+            heat_scratch: HeatScratch::default(),
+        }
+    }
+
+    // Reuses the existing buffers, so the simulation can copy the grid every tick without allocating
+    fn clone_from(&mut self, source: &Self) {
+        self.particles.clone_from(&source.particles);
+        self.velocities.clone_from(&source.velocities);
+        self.temperatures.clone_from(&source.temperatures);
+        self.fuel.clone_from(&source.fuel);
+        self.moved_at.clone_from(&source.moved_at);
+        self.copy_state_from(source);
+        self.written_chunks.resize_with(source.written_chunks.len(), || AtomicBool::new(false));
+        for (written, source) in self.written_chunks.iter_mut().zip(&source.written_chunks) {
+            *written.get_mut() = source.load(Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resizing_keeps_contents_on_the_bottom_and_centred() {
+        let registry = MaterialRegistry::builtin();
+        let sand = registry.sand();
+        let mut grid = Grid::new(registry, 40, 30);
+        grid.spawn(0, 29, sand);
+        grid.spawn(39, 29, sand);
+        grid.spawn(20, 0, sand);
+        grid.set_temperature(20, 0, 300.0);
+
+        let grown = grid.resized(60, 50);
+        assert_eq!((grown.width(), grown.height()), (60, 50));
+        assert_eq!(grown.get(10, 49).material(), sand);
+        assert_eq!(grown.get(49, 49).material(), sand);
+        assert_eq!(grown.get(30, 20).material(), sand);
+        assert_eq!(grown.get_temperature(30, 20), 300.0);
+        assert_eq!(grown.particles.iter().filter(|particle| particle.material() == sand).count(), 3);
 
-    pub fn set_velocity(&mut self, x: usize, y: usize, velocity: f32) {
-        self.velocities[y * GRID_WIDTH + x] = velocity;
+        // Shrinking cuts off the top and both sides evenly
+        let shrunk = grid.resized(20, 10);
+        assert_eq!((shrunk.width(), shrunk.height()), (20, 10));
+        assert!(shrunk.particles.iter().all(|particle| particle.material() != sand));
+        let kept = grid.resized(40, 1);
+        assert_eq!(kept.get(0, 0).material(), sand);
+        assert_eq!(kept.get(39, 0).material(), sand);
     }
 
-    pub fn move_to_with_velocity(&mut self, x1: usize, y1: usize, x2: usize, y2: usize) {
-        if self.get(x2, y2).material_type == Material::Empty as u8 {
-            let particle = self.get(x1, y1);
-            
-            self.set(x2, y2, Material::from_id(particle.material_type));
-            self.set(x1, y1, Material::Empty);
-            
-            self.set_velocity(x2, y2, self.get_velocity(x1, y1));
-            self.set_velocity(x1, y1, 0.0);
+    #[test]
+    fn shades_vary_between_ticks_but_repeat_within_one() {
+        let registry = MaterialRegistry::builtin();
+        let sand = registry.sand();
+        let mut grid = Grid::new(registry, 32, 32);
+        let mut shades = Vec::new();
+        for _ in 0..8 {
+            grid.begin_tick();
+            grid.set(5, 5, sand);
+            let shade = grid.get(5, 5).get_color();
+            grid.set(5, 5, grid.registry().empty());
+            grid.set(5, 5, sand);
+            assert_eq!(grid.get(5, 5).get_color(), shade);
+            shades.push(shade);
         }
+        shades.sort_unstable();
+        shades.dedup();
+        assert!(shades.len() > 1, "every tick spawned the same shade");
     }
 }
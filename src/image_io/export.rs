@@ -0,0 +1,44 @@
+use std::path::Path;
+use image::{Rgba, RgbaImage};
+use crate::grid::Grid;
+
+use super::ImageIoError;
+
+/// Resolution of an exported image
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum ExportScale {
+    /// One pixel per cell
+    #[default]
+    Native,
+    /// As many pixels per cell as shown on screen
+    Screen,
+}
+
+impl ExportScale {
+    /// Pixels per cell, for cells drawn `cell_size` pixels wide on screen
+    pub fn factor(self, cell_size: f32) -> u32 {
+        match self {
+            ExportScale::Native => 1,
+            ExportScale::Screen => (cell_size as u32).max(1),
+        }
+    }
+}
+
+/// Writes the grid's colours to a PNG, each cell a `scale` x `scale` block, creating its directory if needed
+pub fn export_png(grid: &Grid, path: impl AsRef<Path>, scale: u32) -> Result<(), ImageIoError> {
+    let path = path.as_ref();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|source| ImageIoError::Io { path: path.to_path_buf(), source })?;
+    }
+    grid_to_image(grid, scale, Grid::color)
+        .save_with_format(path, image::ImageFormat::Png)
+        .map_err(|source| ImageIoError::from_image(path, source))
+}
+
+/// Draws the grid into an image, each cell a `scale` x `scale` block of the colour `color` picks for it
+pub fn grid_to_image(grid: &Grid, scale: u32, color: impl Fn(&Grid, usize, usize) -> (u8, u8, u8)) -> RgbaImage {
+    RgbaImage::from_fn(grid.width() as u32 * scale, grid.height() as u32 * scale, |px, py| {
+        let (r, g, b) = color(grid, (px / scale) as usize, (py / scale) as usize);
+        Rgba([r, g, b, 255])
+    })
+}
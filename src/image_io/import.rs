@@ -0,0 +1,109 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::Path;
+use image::imageops::{self, FilterType};
+use image::RgbaImage;
+use crate::grid::Grid;
+use crate::materials::Material;
+use crate::registry::MaterialRegistry;
+
+use super::{ImageIoError, Palette};
+
+const OPAQUE_THRESHOLD: u8 = 128; // Pixels less opaque than this are left empty
+
+/// How an image that doesn't match the grid size is fitted onto it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum FitMode {
+    /// Scale the image to the grid size, keeping hard pixel edges
+    #[default]
+    Resize,
+    /// Keep the image at one pixel per cell, centred and cut off at the grid edges
+    Crop,
+}
+
+/// What happened to the pixels of an imported image
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ImportReport {
+    /// Pixels placed on the grid
+    pub pixels: usize,
+    /// Pixels whose colour is neither in the palette nor a shade of one of its materials,
+    /// and were given the nearest entry
+    pub approximated: usize,
+}
+
+/// Reads a PNG and turns it into a `width` x `height` grid, one material per pixel
+pub fn import_png(
+    path: impl AsRef<Path>,
+    registry: &MaterialRegistry,
+    palette: &Palette,
+    fit: FitMode,
+    width: usize,
+    height: usize,
+) -> Result<(Grid, ImportReport), ImageIoError> {
+    let path = path.as_ref();
+    let image = image::open(path)
+        .map_err(|source| ImageIoError::from_image(path, source))?
+        .to_rgba8();
+    Ok(grid_from_image(&image, registry, palette, fit, width, height))
+}
+
+/// Turns an image into a `grid_width` x `grid_height` grid, one material per pixel
+pub fn grid_from_image(
+    image: &RgbaImage,
+    registry: &MaterialRegistry,
+    palette: &Palette,
+    fit: FitMode,
+    grid_width: usize,
+    grid_height: usize,
+) -> (Grid, ImportReport) {
+    let (width, height) = (grid_width as u32, grid_height as u32);
+    let (image, offset) = match fit {
+        FitMode::Resize if image.dimensions() != (width, height) => {
+            (Cow::Owned(imageops::resize(image, width, height, FilterType::Nearest)), (0, 0))
+        }
+        FitMode::Resize => (Cow::Borrowed(image), (0, 0)),
+        FitMode::Crop => {
+            // Smaller images leave an empty border, larger ones lose their edges
+            let offset = (
+                (width as i64 - image.width() as i64) / 2,
+                (height as i64 - image.height() as i64) / 2,
+            );
+            (Cow::Borrowed(image), offset)
+        }
+    };
+
+    let mut grid = Grid::new(registry.clone(), grid_width, grid_height);
+    let mut report = ImportReport::default();
+    // Colours not in the palette, with the material they were matched to and whether it's approximate
+    let mut matched: HashMap<(u8, u8, u8), (Material, bool)> = HashMap::new();
+
+    for (px, py, pixel) in image.enumerate_pixels() {
+        let (x, y) = (px as i64 + offset.0, py as i64 + offset.1);
+        if !grid.in_bounds(x as isize, y as isize) {
+            continue;
+        }
+        let [r, g, b, a] = pixel.0;
+        if a < OPAQUE_THRESHOLD {
+            continue;
+        }
+
+        let color = (r, g, b);
+        let material = match palette.get(color) {
+            Some(material) => material,
+            None => {
+                let (material, approximate) = *matched.entry(color).or_insert_with(|| {
+                    palette.shade_of(color, registry).map_or((palette.nearest(color), true), |material| (material, false))
+                });
+                report.approximated += approximate as usize;
+                material
+            }
+        };
+        report.pixels += 1;
+        if material != registry.empty() {
+            grid.spawn(x as usize, y as usize, material);
+        }
+    }
+
+    grid.wake_all();
+    (grid, report)
+}
@@ -0,0 +1,58 @@
+mod export;
+mod import;
+mod palette;
+mod recording;
+mod stamp;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub use export::{export_png, grid_to_image, ExportScale};
+pub use import::{import_png, FitMode};
+pub use palette::Palette;
+pub use recording::{RecordFormat, Recorder};
+pub use stamp::{load_stamp, Stamp};
+
+/// Errors produced while reading or writing images and palettes
+#[derive(Debug)]
+pub enum ImageIoError {
+    /// A file couldn't be read or written
+    Io { path: PathBuf, source: std::io::Error },
+    /// The image couldn't be decoded or encoded
+    Image { path: PathBuf, source: image::ImageError },
+    /// The palette file isn't valid RON or doesn't match the expected layout
+    PaletteParse(ron::error::SpannedError),
+    /// A palette entry parsed but can't be used
+    InvalidPalette { index: usize, reason: String },
+}
+
+impl ImageIoError {
+    fn from_image(path: &Path, source: image::ImageError) -> Self {
+        match source {
+            image::ImageError::IoError(source) => ImageIoError::Io { path: path.to_path_buf(), source },
+            source => ImageIoError::Image { path: path.to_path_buf(), source },
+        }
+    }
+}
+
+impl fmt::Display for ImageIoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageIoError::Io { path, source } => write!(f, "could not access {}: {source}", path.display()),
+            ImageIoError::Image { path, source } => write!(f, "could not process image {}: {source}", path.display()),
+            ImageIoError::PaletteParse(err) => write!(f, "invalid palette: {err}"),
+            ImageIoError::InvalidPalette { index, reason } => write!(f, "palette entry #{index}: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for ImageIoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageIoError::Io { source, .. } => Some(source),
+            ImageIoError::Image { source, .. } => Some(source),
+            ImageIoError::PaletteParse(err) => Some(err),
+            ImageIoError::InvalidPalette { .. } => None,
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use std::path::Path;
+use serde::Deserialize;
+use crate::materials::Material;
+use crate::registry::MaterialRegistry;
+
+use super::ImageIoError;
+
+/// A palette entry exactly as written in a palette file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PaletteEntry {
+    color: (u8, u8, u8),
+    material: String,
+}
+
+/// Maps image colours to materials
+#[derive(Clone, Debug)]
+pub struct Palette {
+    entries: Vec<((u8, u8, u8), Material)>,
+}
+
+impl Palette {
+    /// Every material's own colour, in registry order
+    pub fn from_registry(registry: &MaterialRegistry) -> Self {
+        let mut palette = Palette { entries: Vec::new() };
+        for (material, definition) in registry.iter() {
+            palette.insert(definition.properties.color, material);
+        }
+        palette
+    }
+
+    /// Parses a RON list of `(color: (r, g, b), material: "Name")` entries
+    pub fn from_ron(source: &str, registry: &MaterialRegistry) -> Result<Self, ImageIoError> {
+        let entries: Vec<PaletteEntry> = ron::from_str(source).map_err(ImageIoError::PaletteParse)?;
+
+        let mut palette = Palette { entries: Vec::with_capacity(entries.len()) };
+        for (index, entry) in entries.iter().enumerate() {
+            let material = registry.find(&entry.material).ok_or_else(|| ImageIoError::InvalidPalette {
+                index,
+                reason: format!("unknown material {:?}", entry.material),
+            })?;
+            if palette.get(entry.color).is_some() {
+                return Err(ImageIoError::InvalidPalette {
+                    index,
+                    reason: format!("colour {:?} is listed more than once", entry.color),
+                });
+            }
+            palette.entries.push((entry.color, material));
+        }
+        if palette.entries.is_empty() {
+            return Err(ImageIoError::InvalidPalette { index: 0, reason: "palette has no entries".into() });
+        }
+        Ok(palette)
+    }
+
+    /// Loads a palette file, or the registry colours if there is no such file
+    pub fn load_or_registry(path: impl AsRef<Path>, registry: &MaterialRegistry) -> Result<Self, ImageIoError> {
+        let path = path.as_ref();
+        match std::fs::read_to_string(path) {
+            Ok(source) => Self::from_ron(&source, registry),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(Self::from_registry(registry)),
+            Err(source) => Err(ImageIoError::Io { path: path.to_path_buf(), source }),
+        }
+    }
+
+    /// The material listed for exactly this colour
+    pub fn get(&self, color: (u8, u8, u8)) -> Option<Material> {
+        self.entries.iter().find(|(entry, _)| *entry == color).map(|(_, material)| *material)
+    }
+
+    /// The first listed material that gives some of its particles exactly this colour
+    pub fn shade_of(&self, color: (u8, u8, u8), registry: &MaterialRegistry) -> Option<Material> {
+        self.entries
+            .iter()
+            .map(|(_, material)| *material)
+            .find(|material| registry.properties(*material).is_shade(color))
+    }
+
+    /// The material whose colour is closest to `color`
+    pub fn nearest(&self, color: (u8, u8, u8)) -> Material {
+        let distance = |(r, g, b): (u8, u8, u8)| {
+            let (dr, dg, db) = (r as i32 - color.0 as i32, g as i32 - color.1 as i32, b as i32 - color.2 as i32);
+            dr * dr + dg * dg + db * db
+        };
+        self.entries
+            .iter()
+            .min_by_key(|(entry, _)| distance(*entry))
+            .map(|(_, material)| *material)
+            .expect("palettes are never empty")
+    }
+
+    // Materials sharing a colour keep the first one listed
+    fn insert(&mut self, color: (u8, u8, u8), material: Material) {
+        if self.get(color).is_none() {
+            self.entries.push((color, material));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PALETTE: &str = r#"[
+        (color: (255, 255, 255), material: "Stone"),
+        (color: (0, 0, 255), material: "Water"),
+        (color: (0, 0, 0), material: "Empty"),
+    ]"#;
+
+    #[test]
+    fn listed_colours_map_to_their_materials() {
+        let registry = MaterialRegistry::builtin();
+        let palette = Palette::from_ron(PALETTE, &registry).unwrap();
+        assert_eq!(palette.get((255, 255, 255)), registry.find("Stone"));
+        assert_eq!(palette.get((0, 0, 255)), registry.find("Water"));
+        assert_eq!(palette.get((0, 0, 0)), Some(registry.empty()));
+        assert_eq!(palette.get((1, 0, 0)), None);
+    }
+
+    #[test]
+    fn unlisted_colours_get_the_nearest_entry() {
+        let registry = MaterialRegistry::builtin();
+        let palette = Palette::from_ron(PALETTE, &registry).unwrap();
+        assert_eq!(Some(palette.nearest((230, 240, 220))), registry.find("Stone"));
+        assert_eq!(Some(palette.nearest((20, 30, 200))), registry.find("Water"));
+        assert_eq!(palette.nearest((30, 20, 40)), registry.empty());
+    }
+
+    #[test]
+    fn shades_map_to_the_material_they_vary_from() {
+        let registry = MaterialRegistry::builtin();
+        let palette = Palette::from_registry(&registry);
+        let wood = registry.find("Wood").unwrap();
+        let (r, g, b) = registry.properties(wood).color;
+        assert_eq!(palette.get((r, g, b)), Some(wood));
+        assert_eq!(palette.shade_of((r + 2, g + 2, b + 2), &registry), Some(wood));
+        assert_eq!(palette.shade_of((255, 0, 255), &registry), None);
+    }
+
+    #[test]
+    fn bad_entries_are_rejected_with_their_index() {
+        let registry = MaterialRegistry::builtin();
+        let invalid = |source: &str| match Palette::from_ron(source, &registry) {
+            Err(ImageIoError::InvalidPalette { index, reason }) => (index, reason),
+            other => panic!("expected an invalid palette, got {other:?}"),
+        };
+        let unknown = r#"[(color: (1, 2, 3), material: "Sand"), (color: (4, 5, 6), material: "Cheese")]"#;
+        assert_eq!(invalid(unknown), (1, "unknown material \"Cheese\"".into()));
+        let repeated = r#"[(color: (1, 2, 3), material: "Sand"), (color: (1, 2, 3), material: "Water")]"#;
+        assert_eq!(invalid(repeated), (1, "colour (1, 2, 3) is listed more than once".into()));
+        assert_eq!(invalid("[]"), (0, "palette has no entries".into()));
+    }
+}
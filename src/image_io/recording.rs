@@ -0,0 +1,129 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::{Delay, Frame, RgbaImage};
+
+use super::ImageIoError;
+
+const GIF_SPEED: i32 = 10; // Quantizer speed from 1 (best) to 30 (fastest), only used past 256 colours
+const MAX_QUEUED_FRAMES: usize = 8; // Frames waiting to be written before new ones are dropped
+
+/// File layout of a recording
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RecordFormat {
+    /// A single looping animated GIF
+    #[default]
+    Gif,
+    /// Numbered PNG frames in a directory named after the file
+    PngSequence,
+}
+
+impl RecordFormat {
+    /// Where a recording started at `path` ends up: the file itself for GIFs,
+    /// a directory named after the file, minus its extension, for PNG sequences
+    pub fn output_path(self, path: &Path) -> PathBuf {
+        match self {
+            RecordFormat::Gif => path.to_path_buf(),
+            RecordFormat::PngSequence => path.with_extension(""),
+        }
+    }
+}
+
+/// Writes frames to disk on a background thread, so encoding doesn't stall rendering.
+/// Frames arriving while the writer is behind by `MAX_QUEUED_FRAMES` are dropped.
+pub struct Recorder {
+    frames: usize,
+    dropped: usize,
+    dropped_delay: Duration, // Playback time of the frames dropped since the last queued one
+    sender: Option<SyncSender<(RgbaImage, Duration)>>,
+    worker: Option<JoinHandle<Result<(), ImageIoError>>>,
+}
+
+impl Recorder {
+    /// Starts a recording at `path`, see `RecordFormat::output_path`
+    pub fn start(path: impl AsRef<Path>, format: RecordFormat) -> Result<Self, ImageIoError> {
+        let path = path.as_ref().to_path_buf();
+        let io_error = |path: &Path| {
+            let path = path.to_path_buf();
+            move |source| ImageIoError::Io { path, source }
+        };
+
+        let (sender, receiver) = mpsc::sync_channel::<(RgbaImage, Duration)>(MAX_QUEUED_FRAMES);
+        let worker = match format {
+            RecordFormat::Gif => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent).map_err(io_error(parent))?;
+                }
+                let file = File::create(&path).map_err(io_error(&path))?;
+                std::thread::spawn(move || {
+                    let mut encoder = GifEncoder::new_with_speed(BufWriter::new(file), GIF_SPEED);
+                    encoder.set_repeat(Repeat::Infinite).map_err(|source| ImageIoError::from_image(&path, source))?;
+                    for (image, delay) in receiver {
+                        let frame = Frame::from_parts(image, 0, 0, Delay::from_saturating_duration(delay));
+                        encoder.encode_frame(frame).map_err(|source| ImageIoError::from_image(&path, source))?;
+                    }
+                    Ok(())
+                })
+            }
+            RecordFormat::PngSequence => {
+                let directory = format.output_path(&path);
+                std::fs::create_dir_all(&directory).map_err(io_error(&directory))?;
+                std::thread::spawn(move || {
+                    for (index, (image, _)) in receiver.into_iter().enumerate() {
+                        let path = directory.join(format!("frame_{index:05}.png"));
+                        image
+                            .save_with_format(&path, image::ImageFormat::Png)
+                            .map_err(|source| ImageIoError::from_image(&path, source))?;
+                    }
+                    Ok(())
+                })
+            }
+        };
+
+        Ok(Self { frames: 0, dropped: 0, dropped_delay: Duration::ZERO, sender: Some(sender), worker: Some(worker) })
+    }
+
+    /// Queues a frame, shown for `delay` when played back, or drops it if the queue is full.
+    /// Returns false once writing has failed; `finish` then reports why.
+    pub fn push(&mut self, frame: RgbaImage, delay: Duration) -> bool {
+        let Some(sender) = self.sender.as_ref() else { return false };
+        // The next queued frame stays up for the dropped ones, so playback keeps its speed
+        match sender.try_send((frame, delay + self.dropped_delay)) {
+            Ok(()) => {
+                self.frames += 1;
+                self.dropped_delay = Duration::ZERO;
+            }
+            Err(TrySendError::Full(_)) => {
+                self.dropped += 1;
+                self.dropped_delay += delay;
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+        true
+    }
+
+    /// Frames queued so far
+    pub fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Frames dropped so far because the writer fell behind
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Waits for every queued frame to be written
+    pub fn finish(mut self) -> Result<usize, ImageIoError> {
+        // Closing the channel ends the worker's loop
+        self.sender.take();
+        match self.worker.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result.map(|()| self.frames),
+            Some(Err(panic)) => std::panic::resume_unwind(panic),
+            None => Ok(self.frames),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use super::ImageIoError;
+
+const OPAQUE_THRESHOLD: u8 = 128; // Pixels less opaque than this are left out of the stamp
+
+/// A bitmap brush: the opaque pixels of an image mark the cells it paints
+#[derive(Clone, Debug)]
+pub struct Stamp {
+    width: usize,
+    height: usize,
+    cells: Vec<bool>,
+}
+
+impl Stamp {
+    /// Offsets of the painted cells from the stamp centre, scaled so the stamp's
+    /// longest side spans `size` cells
+    pub fn offsets(&self, size: usize) -> Vec<(isize, isize)> {
+        if self.cells.is_empty() {
+            return Vec::new();
+        }
+        let scale = size.max(1) as f32 / self.width.max(self.height) as f32;
+        let width = ((self.width as f32 * scale).round() as usize).max(1);
+        let height = ((self.height as f32 * scale).round() as usize).max(1);
+
+        let mut offsets = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                // Nearest-neighbour sampling at pixel centres keeps the stamp's hard edges
+                let source_x = (((x as f32 + 0.5) / scale) as usize).min(self.width - 1);
+                let source_y = (((y as f32 + 0.5) / scale) as usize).min(self.height - 1);
+                if self.cells[source_y * self.width + source_x] {
+                    offsets.push((x as isize - width as isize / 2, y as isize - height as isize / 2));
+                }
+            }
+        }
+        offsets
+    }
+}
+
+/// Reads a PNG to use as a brush stamp
+pub fn load_stamp(path: impl AsRef<Path>) -> Result<Stamp, ImageIoError> {
+    let path = path.as_ref();
+    let image = image::open(path)
+        .map_err(|err| ImageIoError::from_image(path, err))?
+        .to_rgba8();
+
+    Ok(Stamp {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        cells: image.pixels().map(|pixel| pixel[3] >= OPAQUE_THRESHOLD).collect(),
+    })
+}
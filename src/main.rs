@@ -1,33 +1,38 @@
 use bevy::prelude::*;
 use bevy_egui::EguiPlugin;
-use crate::config::WindowConfig;
+use crate::settings::Settings;
 
 mod config;
+mod settings;
 mod systems;
 mod materials;
 mod grid;
 mod utils;
 mod registry;
+mod save;
+mod image_io;
 mod plugins;
 
 use plugins::{SimulationPlugin, InputPlugin, UIPlugin};
 
 fn main() {
-    let window_config = WindowConfig::new();
-    
+    let settings = Settings::from_env();
+    let window_size = settings.window_size();
+
     App::new()
         .add_plugins(DefaultPlugins
             .set(WindowPlugin {
                 primary_window: Some(Window {
                     title: "Falling Sand Simulator".into(),
-                    resolution: (window_config.width, window_config.height).into(),
-                    resizable: false,
+                    resolution: (window_size.x, window_size.y).into(),
+                    resizable: true,
                     ..default()
                 }),
                 ..default()
             })
             .set(bevy::render::texture::ImagePlugin::default_nearest()))
         .add_plugins(EguiPlugin)
+        .insert_resource(settings)
         .add_plugins((
             SimulationPlugin,
             InputPlugin,
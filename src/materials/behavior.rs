@@ -1,14 +1,17 @@
-use crate::{grid::Grid, utils::grid_utils::find_vertical_space};
-use crate::materials::{Material, types::ParticleFlags};
-use crate::config;
-use rand::{prelude::*, rng};
+use crate::grid::{Grid, GridWriter};
+use crate::utils::line::bresenham_line;
+use crate::materials::{Explosion, Material, Reaction, types::ParticleFlags};
+use bevy::math::Vec2;
+use rand::prelude::*;
 use crate::utils::grid_utils::find_horizontal_space;
 
-use super::types::Particle;
-
 const GRAVITY: f32 = 0.5;
-const MAX_FALL_SPEED: f32 = 8.0;
+pub const MAX_REACH: usize = 8;  // Furthest a particle reads or writes from its own cell in one update
+const MAX_SPEED: f32 = MAX_REACH as f32;
 const BOUNCE_FACTOR: f32 = 0.3;
+const SPLASH_FACTOR: f32 = 0.8;   // Share of landing speed turned into sideways motion
+const AIR_DRAG: f32 = 0.98;       // Sideways speed kept per tick while flying
+const GROUND_FRICTION: f32 = 0.8; // Sideways speed kept per tick while sliding
 const MIN_MOVEMENT_THRESHOLD: f32 = 0.1;
 const FIRE_DISSIPATION_CHANCE: f32 = 0.4; // 10% for connected fire
 const FIRE_ISOLATED_DISSIPATION_CHANCE: f32 = 0.7; // 50% for isolated fire
@@ -19,14 +22,21 @@ const FIRE_COLORS: [(u8, u8, u8); 4] = [
     (255, 160, 30),  // Yellow-orange
     (255, 50, 0),    // Deep orange
 ];
-const SMOKE_DISSIPATION_CHANCE: f32 = 0.1; // 30% chance to disappear
 const FIRE_TO_SMOKE_CHANCE: f32 = 0.2; // 30% chance for dissipating fire to become smoke
+const BURNING_FLAME_CHANCE: f32 = 0.3; // Chance per tick for a burning particle to throw a flame
+const BURNING_SMOKE_CHANCE: f32 = 0.05; // Chance per tick for a burning particle to give off smoke
+const FLAME_DIRECTIONS: [(isize, isize); 5] = [(0, -1), (-1, -1), (1, -1), (-1, 0), (1, 0)];
+const NEIGHBOURS: [(isize, isize); 8] = [
+    (-1, -1), (0, -1), (1, -1),
+    (-1, 0),           (1, 0),
+    (-1, 1),  (0, 1),  (1, 1),
+];
 pub trait MaterialBehavior {
-    fn update(&self, x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid);
+    fn update(&self, x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng);
 }
 
 impl MaterialBehavior for Material {
-    fn update(&self, x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) {
+    fn update(&self, x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) {
         // Skip if already updated in this frame
         if new_grid.get(x, y).material_type != old_grid.get(x, y).material_type {
             return;
@@ -34,57 +44,193 @@ impl MaterialBehavior for Material {
 
         let particle = old_grid.get(x, y);
 
+        // Handle reactions with touching materials
+        if try_react(x, y, new_grid, old_grid, rng) {
+            return;
+        }
+
         // Handle fire behavior separately
-        if particle.material_type == Material::Fire as u8 {
-            if handle_fire(x, y, new_grid, old_grid) {
-                return;
-            }
+        if particle.material() == old_grid.registry().fire() && handle_fire(x, y, new_grid, old_grid, rng) {
+            return;
         }
 
-        // Handle smoke dissipation
-        if particle.material_type == Material::Smoke as u8 {
-            if rng().gen::<f32>() < SMOKE_DISSIPATION_CHANCE {
-                new_grid.set(x, y, Material::Empty);
+        // Handle ignition and burning of flammable materials
+        if particle.flags.contains(ParticleFlags::BURNING) {
+            if burn(x, y, new_grid, old_grid, rng) {
                 return;
             }
+        } else if try_ignite(x, y, new_grid, old_grid, rng) {
+            return;
+        }
+
+        // Handle temperature-driven and spontaneous material changes
+        if try_transition(x, y, new_grid, old_grid, rng) {
+            return;
         }
 
         // If not movable, just copy the state
         if !particle.flags.contains(ParticleFlags::MOVABLE) {
-            new_grid.set(x, y, *self);
+            new_grid.set_particle(x, y, particle);
             return;
         }
 
         // Remaining logic for other materials (unchanged)
-        if try_move_density_based(x, y, new_grid, old_grid) {
+        if try_move_density_based(x, y, new_grid, old_grid, rng) {
             return;
         }
         if particle.flags.contains(ParticleFlags::RISES) {
-            if rise(x, y, new_grid, old_grid) {
+            if rise(x, y, new_grid, old_grid, rng) {
                 return;
             }
         } else {
-            if fall(x, y, new_grid, old_grid) {
+            if fall(x, y, new_grid, old_grid, rng) {
                 return;
             }
         }
         if particle.flags.contains(ParticleFlags::FLOWS) {
-            flow(x, y, new_grid, old_grid);
+            flow(x, y, new_grid, old_grid, rng);
             return;
         }
-        new_grid.set(x, y, *self);
+        new_grid.set_particle(x, y, particle);
+    }
+}
+
+fn try_react(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
+    let particle = old_grid.get(x, y);
+    let reactions = old_grid.registry().reactions(particle.material());
+    if reactions.is_empty() {
+        return false;
+    }
+
+    // Start from a random neighbour so no direction is favoured
+    let start = rng.random_range(0..NEIGHBOURS.len());
+    for i in 0..NEIGHBOURS.len() {
+        let (dx, dy) = NEIGHBOURS[(start + i) % NEIGHBOURS.len()];
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        if !old_grid.in_bounds(nx, ny) {
+            continue;
+        }
+        let (nx, ny) = (nx as usize, ny as usize);
+
+        // Skip neighbours that already moved or changed this tick
+        let other = old_grid.get(nx, ny).material();
+        if new_grid.get(nx, ny).material() != other {
+            continue;
+        }
+
+        for reaction in reactions.iter().filter(|reaction| reaction.with == other) {
+            if rng.random::<f32>() < reaction.chance {
+                apply_reaction(reaction, x, y, nx, ny, new_grid, rng);
+                return true;
+            }
+            // Stay awake until the reaction goes through
+            new_grid.wake(x, y);
+        }
+    }
+    false
+}
+
+fn apply_reaction(reaction: &Reaction, x: usize, y: usize, nx: usize, ny: usize, grid: &GridWriter, rng: &mut impl Rng) {
+    if let Some(into) = reaction.into {
+        grid.spawn(x, y, into);
+    }
+    if let Some(other_into) = reaction.other_into {
+        grid.spawn(nx, ny, other_into);
+    }
+    if reaction.heat != 0.0 {
+        grid.set_temperature(x, y, grid.get_temperature(x, y) + reaction.heat);
+        grid.set_temperature(nx, ny, grid.get_temperature(nx, ny) + reaction.heat);
+    }
+
+    if let Some(emit) = reaction.emit {
+        let start = rng.random_range(0..NEIGHBOURS.len());
+        let directions = (0..NEIGHBOURS.len()).map(|i| NEIGHBOURS[(start + i) % NEIGHBOURS.len()]);
+        spawn_in_free_cell(x, y, emit, directions, grid);
+    }
+}
+
+/// Spawns a material into the first empty cell around (x, y), trying directions in order
+fn spawn_in_free_cell(x: usize, y: usize, material: Material, directions: impl IntoIterator<Item = (isize, isize)>, grid: &GridWriter) -> bool {
+    for (dx, dy) in directions {
+        let (nx, ny) = (x as isize + dx, y as isize + dy);
+        if grid.in_bounds(nx, ny) && grid.is_empty(nx as usize, ny as usize) {
+            grid.spawn(nx as usize, ny as usize, material);
+            return true;
+        }
+    }
+    false
+}
+
+fn try_ignite(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
+    let particle = old_grid.get(x, y);
+    let props = old_grid.registry().properties(particle.material());
+    if props.flammability <= 0.0 || !has_fire_neighbors(x, y, old_grid) {
+        return false;
+    }
+    if rng.random::<f32>() >= props.flammability {
+        new_grid.wake(x, y);
+        return false;
+    }
+
+    let mut burning = particle;
+    burning.flags |= ParticleFlags::BURNING;
+    new_grid.set_particle(x, y, burning);
+    new_grid.set_fuel(x, y, props.fuel);
+    true
+}
+
+/// Consumes a tick of fuel, throwing flames and smoke while it lasts.
+/// Returns true once the particle has burnt out.
+fn burn(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
+    let particle = old_grid.get(x, y);
+    let fuel = old_grid.get_fuel(x, y);
+
+    // Whatever is left keeps the heat of the fire
+    if fuel == 0 {
+        let props = old_grid.registry().properties(particle.material());
+        new_grid.set(x, y, props.burns_into);
+        if props.blast_radius > 0 {
+            new_grid.queue_explosion(Explosion::new(x, y, props.blast_radius as f32));
+        }
+        return true;
+    }
+    new_grid.set_fuel(x, y, fuel - 1);
+
+    // Burning particles are as hot as the flames they give off
+    let fire_temperature = old_grid.registry().properties(old_grid.registry().fire()).temperature;
+    new_grid.set_temperature(x, y, old_grid.get_temperature(x, y).max(fire_temperature));
+
+    if rng.random::<f32>() < BURNING_FLAME_CHANCE {
+        spawn_in_free_cell(x, y, old_grid.registry().fire(), FLAME_DIRECTIONS, new_grid);
+    }
+    if rng.random::<f32>() < BURNING_SMOKE_CHANCE {
+        spawn_in_free_cell(x, y, old_grid.registry().smoke(), FLAME_DIRECTIONS, new_grid);
+    }
+    false
+}
+
+fn try_transition(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
+    let particle = old_grid.get(x, y);
+    let temperature = old_grid.get_temperature(x, y);
+
+    for transition in old_grid.registry().transitions(particle.material()) {
+        if transition.applies_at(temperature) && rng.random::<f32>() < transition.chance {
+            new_grid.set(x, y, transition.into);
+            return true;
+        }
     }
+    false
 }
 
-fn try_move_density_based(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool {
+fn try_move_density_based(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
     let current_particle = old_grid.get(x, y);
     let current_density = current_particle.get_density();
 
-    let mut check_and_swap = |x2: usize, y2: usize| -> bool {
+    let check_and_swap = |x2: usize, y2: usize| -> bool {
         let other_particle = old_grid.get(x2, y2);
         
         // Only interact with non-empty particles
-        if other_particle.material_type != Material::Empty as u8 {
+        if other_particle.material() != old_grid.registry().empty() {
             let other_density = other_particle.get_density();
             if current_density > other_density {
                 new_grid.swap(x, y, x2, y2);
@@ -95,18 +241,18 @@ fn try_move_density_based(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Gr
     };
 
     // Check below first
-    if y < config::GRID_HEIGHT - 1 && check_and_swap(x, y + 1) {
+    if y < old_grid.height() - 1 && check_and_swap(x, y + 1) {
         return true;
     }
 
     // Then check diagonally below
-    if y < config::GRID_HEIGHT - 1 {
+    if y < old_grid.height() - 1 {
         let left = x > 0;
-        let right = x < config::GRID_WIDTH - 1;
+        let right = x < old_grid.width() - 1;
         
         match (left, right) {
             (true, true) => {
-                if rng().random::<bool>() {
+                if rng.random::<bool>() {
                     if check_and_swap(x - 1, y + 1) { return true; }
                     if check_and_swap(x + 1, y + 1) { return true; }
                 } else {
@@ -123,18 +269,18 @@ fn try_move_density_based(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Gr
     false
 }
 
-fn rise(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool {
-    if y > 0 && old_grid.get(x, y - 1).material_type == Material::Empty as u8 {
+fn rise(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
+    if y > 0 && old_grid.is_empty(x, y - 1) {
         new_grid.move_to(x, y, x, y - 1);
         return true;
     }
     if y > 0 {
-        let left = x > 0 && old_grid.get(x - 1, y - 1).material_type == Material::Empty as u8;
-        let right = x < config::GRID_WIDTH - 1 && old_grid.get(x + 1, y - 1).material_type == Material::Empty as u8;
+        let left = x > 0 && old_grid.is_empty(x - 1, y - 1);
+        let right = x < old_grid.width() - 1 && old_grid.is_empty(x + 1, y - 1);
         
         match (left, right) {
             (true, true) => {
-                if rng().random::<bool>() {
+                if rng.random::<bool>() {
                     new_grid.move_to(x, y, x - 1, y - 1)
                 } else {
                     new_grid.move_to(x, y, x + 1, y - 1)
@@ -156,125 +302,147 @@ fn rise(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool {
     }
 }
 
-fn fall(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool {
-    let mut current_velocity = old_grid.get_velocity(x, y);
+fn fall(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
     let current_particle = old_grid.get(x, y);
-    
-    // Always apply gravity if there's space below
-    if y < config::GRID_HEIGHT - 1 && old_grid.get(x, y + 1).material_type == Material::Empty as u8 {
-        current_velocity += GRAVITY;
-    } else if current_velocity < MIN_MOVEMENT_THRESHOLD {
+    let mut velocity = old_grid.get_velocity(x, y);
+
+    // Gravity pulls while there is room below or the particle is still flying upwards
+    let space_below = y < old_grid.height() - 1 && old_grid.is_empty(x, y + 1);
+    if space_below || velocity.y < 0.0 {
+        velocity.y += GRAVITY;
+    } else if velocity.length() < MIN_MOVEMENT_THRESHOLD {
         // If blocked and nearly stopped, fully stop
-        current_velocity = 0.0;
-        new_grid.set(x, y, Material::from_id(current_particle.material_type));
-        new_grid.set_velocity(x, y, current_velocity);
+        new_grid.set_particle(x, y, current_particle);
+        new_grid.set_velocity(x, y, Vec2::ZERO);
         return false;
     }
+    velocity.x *= AIR_DRAG;
+    velocity = velocity.clamp_length_max(MAX_SPEED);
 
-    current_velocity = current_velocity.min(MAX_FALL_SPEED);
+    // Transfer momentum to nearby particles of the same type, slowing down in exchange
+    if velocity.length() > 1.0 {
+        let transfer = velocity * 0.2; // Transfer 20% of velocity
+        let mut transferred = false;
 
-    // Transfer velocity to nearby particles of the same type
-    if current_velocity > 1.0 {
-        let transfer_amount = current_velocity * 0.2; // Transfer 20% of velocity
-        
         // Check adjacent cells
-        let check_positions = [
-            (x.wrapping_sub(1), y), // Left
-            (x + 1, y),            // Right
-            (x, y.wrapping_sub(1)), // Up
-            (x + 1, y.wrapping_sub(1)), // Up-Right
-            (x.wrapping_sub(1), y.wrapping_sub(1)), // Up-Left
-            (x, y + 1),            // Down
-            (x + 1, y + 1),        // Down-Right
-            (x.wrapping_sub(1), y + 1), // Down-Left
-        ];
-
-        for (nx, ny) in check_positions {
-            if nx < config::GRID_WIDTH && ny < config::GRID_HEIGHT {
-                let neighbor = old_grid.get(nx, ny);
-                if neighbor.material_type == current_particle.material_type {
-                    let neighbor_velocity = old_grid.get_velocity(nx, ny);
-                    if neighbor_velocity < current_velocity {
-                        new_grid.set_velocity(nx, ny, neighbor_velocity + transfer_amount);
-                    }
+        for (dx, dy) in NEIGHBOURS {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if !old_grid.in_bounds(nx, ny) {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            let neighbor = old_grid.get(nx, ny);
+            if neighbor.material_type == current_particle.material_type {
+                let neighbor_velocity = old_grid.get_velocity(nx, ny);
+                if neighbor_velocity.length() < velocity.length() {
+                    new_grid.set_velocity(nx, ny, neighbor_velocity + transfer);
+                    transferred = true;
                 }
             }
         }
-        
-        current_velocity *= 0.8;
+
+        if transferred {
+            velocity *= 0.8;
+        }
     }
 
-    let fall_distance = (current_velocity.abs().round() as usize).max(1);
-    let bottom_y = find_vertical_space(old_grid, x, y, fall_distance);
-    
-    if bottom_y > y + 1 {
-        current_velocity += GRAVITY * ((bottom_y - y) as f32);
-        current_velocity = current_velocity.min(MAX_FALL_SPEED);
+    // Particles resting on something slow down as they slide
+    if !space_below {
+        velocity.x *= GROUND_FRICTION;
     }
-    
-    if bottom_y == y {
-        if current_velocity > 1.0 {
-            let left_clear = x > 0 && old_grid.get(x - 1, y).material_type == Material::Empty as u8;
-            let right_clear = x < config::GRID_WIDTH - 1 && old_grid.get(x + 1, y).material_type == Material::Empty as u8;
-            
-            match (left_clear, right_clear) {
-                (true, true) => {
-                    if rng().gen::<bool>() {
-                        new_grid.move_to_with_velocity(x, y, x - 1, y);
-                        new_grid.set_velocity(x - 1, y, current_velocity * 0.8);
-                    } else {
-                        new_grid.move_to_with_velocity(x, y, x + 1, y);
-                        new_grid.set_velocity(x + 1, y, current_velocity * 0.8);
-                    }
-                    return true;
-                }
-                (true, false) => {
-                    new_grid.move_to_with_velocity(x, y, x - 1, y);
-                    new_grid.set_velocity(x - 1, y, current_velocity * 0.8);
-                    return true;
-                }
-                (false, true) => {
-                    new_grid.move_to_with_velocity(x, y, x + 1, y);
-                    new_grid.set_velocity(x + 1, y, current_velocity * 0.8);
-                    return true;
-                }
-                _ => {
-                    current_velocity *= -BOUNCE_FACTOR;
-                    if current_velocity.abs() < MIN_MOVEMENT_THRESHOLD {
-                        current_velocity = 0.0;
-                    }
-                    new_grid.set(x, y, Material::from_id(current_particle.material_type));
-                    new_grid.set_velocity(x, y, current_velocity);
-                    return false;
-                }
+
+    // Trace the path covered this tick, stopping in front of the first obstacle
+    let wanted = step_target(x, y, velocity);
+    let target = (
+        wanted.0.clamp(0, old_grid.width() as isize - 1) as usize,
+        wanted.1.clamp(0, old_grid.height() as isize - 1) as usize,
+    );
+    let mut landing = (x, y);
+    let mut obstacle = None;
+    if target != (x, y) {
+        let path = bresenham_line(x, y, target.0, target.1).skip(1).chain(std::iter::once(target));
+        for (px, py) in path {
+            if !old_grid.is_empty(px, py) || !new_grid.is_empty(px, py) {
+                obstacle = Some((px as isize - landing.0 as isize, py as isize - landing.1 as isize));
+                break;
             }
-        } else {
-            new_grid.set(x, y, Material::from_id(current_particle.material_type));
-            new_grid.set_velocity(x, y, 0.0);
-            return false;
+            landing = (px, py);
         }
     }
+    // The grid edges stop particles like any other obstacle
+    if obstacle.is_none() && landing == target && (target.0 as isize, target.1 as isize) != wanted {
+        obstacle = Some(((wanted.0 - target.0 as isize).signum(), (wanted.1 - target.1 as isize).signum()));
+    }
 
-    new_grid.move_to_with_velocity(x, y, x, bottom_y);
-    new_grid.set_velocity(x, bottom_y, current_velocity);
-    true
+    if let Some((dx, dy)) = obstacle {
+        velocity = collide(velocity, dx, dy, rng);
+    }
+
+    if landing != (x, y) {
+        new_grid.move_to_with_velocity(x, y, landing.0, landing.1, velocity);
+        return true;
+    }
+
+    new_grid.set_particle(x, y, current_particle);
+    new_grid.set_velocity(x, y, velocity);
+    false
+}
+
+/// Picks the cell a particle moving at `velocity` aims for this tick, which may lie outside the grid
+fn step_target(x: usize, y: usize, velocity: Vec2) -> (isize, isize) {
+    let mut step = velocity.round();
+    // Slow particles still creep one cell at a time in the direction they fall
+    if step == Vec2::ZERO && velocity.y > 0.0 {
+        step.y = 1.0;
+    }
+    (x as isize + step.x as isize, y as isize + step.y as isize)
 }
 
-fn flow(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) {
-    let mut rng = rng();
+/// Bounces a velocity off an obstacle at offset (dx, dy) from the particle.
+/// Hard landings splash sideways instead of stopping dead.
+fn collide(mut velocity: Vec2, dx: isize, dy: isize, rng: &mut impl Rng) -> Vec2 {
+    if dy != 0 {
+        let impact = velocity.y.abs();
+        velocity.y *= -BOUNCE_FACTOR;
+        // Gentle landings settle instead of bouncing forever on a single tick of gravity
+        if impact <= 1.0 || velocity.y.abs() < MIN_MOVEMENT_THRESHOLD {
+            velocity.y = 0.0;
+        }
+        if dy > 0 && impact > 1.0 && velocity.x.abs() < MIN_MOVEMENT_THRESHOLD {
+            let side = if rng.random::<bool>() { 1.0 } else { -1.0 };
+            velocity.x = side * impact * SPLASH_FACTOR;
+        }
+    }
+    if dx != 0 {
+        velocity.x *= -BOUNCE_FACTOR;
+        if velocity.x.abs() < MIN_MOVEMENT_THRESHOLD {
+            velocity.x = 0.0;
+        }
+    }
+    velocity
+}
+
+fn flow(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) {
     let current_particle = old_grid.get(x, y);
     let viscosity = current_particle.get_viscosity();
     
     let max_distance = ((1.0 - viscosity) * 5.0).round() as usize;
-    if max_distance == 0 || rng.gen::<f32>() <= viscosity {
-        new_grid.set(x, y, Material::from_id(current_particle.material_type));
+    if max_distance == 0 {
+        new_grid.set_particle(x, y, current_particle);
         return;
     }
 
     let (left_bound, right_bound) = find_horizontal_space(old_grid, x, y, max_distance);
     
     if left_bound == x && right_bound == x {
-        new_grid.set(x, y, Material::from_id(current_particle.material_type));
+        new_grid.set_particle(x, y, current_particle);
+        return;
+    }
+
+    // Thick liquids only flow some of the time, but stay awake while they have room to
+    if rng.random::<f32>() <= viscosity {
+        new_grid.set_particle(x, y, current_particle);
+        new_grid.wake(x, y);
         return;
     }
 
@@ -283,37 +451,37 @@ fn flow(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) {
         let mut target_x = x;
 
         if left_bound < x && right_bound > x {
-            if rng.gen::<bool>() {
+            if rng.random::<bool>() {
                 let max_left = x - left_bound;
-                let move_amount = if rng.gen::<f32>() < 0.7 {
+                let move_amount = if rng.random::<f32>() < 0.7 {
                     max_left
                 } else {
-                    rng.gen_range(1..=max_left)
+                    rng.random_range(1..=max_left)
                 };
                 target_x = x - move_amount;
             } else {
                 let max_right = right_bound - x;
-                let move_amount = if rng.gen::<f32>() < 0.7 {
+                let move_amount = if rng.random::<f32>() < 0.7 {
                     max_right
                 } else {
-                    rng.gen_range(1..=max_right)
+                    rng.random_range(1..=max_right)
                 };
                 target_x = x + move_amount;
             }
         } else if left_bound < x {
             let max_left = x - left_bound;
-            let move_amount = if rng.gen::<f32>() < 0.7 {
+            let move_amount = if rng.random::<f32>() < 0.7 {
                 max_left
             } else {
-                rng.gen_range(1..=max_left)
+                rng.random_range(1..=max_left)
             };
             target_x = x - move_amount;
         } else if right_bound > x {
             let max_right = right_bound - x;
-            let move_amount = if rng.gen::<f32>() < 0.7 {
+            let move_amount = if rng.random::<f32>() < 0.7 {
                 max_right
             } else {
-                rng.gen_range(1..=max_right)
+                rng.random_range(1..=max_right)
             };
             target_x = x + move_amount;
         }
@@ -324,23 +492,17 @@ fn flow(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) {
         }
     }
 
-    new_grid.set(x, y, Material::from_id(current_particle.material_type));
+    new_grid.set_particle(x, y, current_particle);
 }
 
+/// Checks for fire or burning particles around (x, y)
 fn has_fire_neighbors(x: usize, y: usize, grid: &Grid) -> bool {
-    let directions = [
-        (-1, -1), (0, -1), (1, -1), // Above
-        (-1, 0),           (1, 0),  // Sides
-        (-1, 1),  (0, 1),  (1, 1), // Below
-    ];
-
-    for (dx, dy) in directions.iter() {
+    for (dx, dy) in NEIGHBOURS.iter() {
         let new_x = x as isize + dx;
         let new_y = y as isize + dy;
-        if new_x >= 0 && new_x < config::GRID_WIDTH as isize &&
-           new_y >= 0 && new_y < config::GRID_HEIGHT as isize {
+        if grid.in_bounds(new_x, new_y) {
             let neighbor = grid.get(new_x as usize, new_y as usize);
-            if neighbor.material_type == Material::Fire as u8 {
+            if neighbor.material() == grid.registry().fire() || neighbor.flags.contains(ParticleFlags::BURNING) {
                 return true;
             }
         }
@@ -348,8 +510,7 @@ fn has_fire_neighbors(x: usize, y: usize, grid: &Grid) -> bool {
     false
 }
 
-fn handle_fire(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool {
-    let mut rng = rng();
+fn handle_fire(x: usize, y: usize, new_grid: &GridWriter, old_grid: &Grid, rng: &mut impl Rng) -> bool {
 
     // Determine dissipation chance based on isolation
     let is_isolated = !has_fire_neighbors(x, y, old_grid);
@@ -360,20 +521,20 @@ fn handle_fire(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool
     };
 
     // Handle dissipation with chance to create smoke
-    if rng.gen::<f32>() < dissipation_chance {
-        if rng.gen::<f32>() < FIRE_TO_SMOKE_CHANCE {
-            new_grid.set(x, y, Material::Smoke);
+    if rng.random::<f32>() < dissipation_chance {
+        if rng.random::<f32>() < FIRE_TO_SMOKE_CHANCE {
+            new_grid.set(x, y, old_grid.registry().smoke());
         } else {
-            new_grid.set(x, y, Material::Empty);
+            new_grid.set(x, y, old_grid.registry().empty());
         }
         return true;
     }
 
     // Preserve existing upward movement logic
     let upward_bias = if y > 0 {
-        let above = old_grid.get(x, y - 1).material_type == Material::Empty as u8;
-        let above_left = x > 0 && old_grid.get(x - 1, y - 1).material_type == Material::Empty as u8;
-        let above_right = x < config::GRID_WIDTH - 1 && old_grid.get(x + 1, y - 1).material_type == Material::Empty as u8;
+        let above = old_grid.is_empty(x, y - 1);
+        let above_left = x > 0 && old_grid.is_empty(x - 1, y - 1);
+        let above_right = x < old_grid.width() - 1 && old_grid.is_empty(x + 1, y - 1);
         
         match (above, above_left, above_right) {
             (true, _, _) => 0.8,    // Strongly prefer moving straight up
@@ -387,28 +548,27 @@ fn handle_fire(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool
     };
 
     // Randomize fire color
-    let color_idx = (rng.gen::<f32>() * FIRE_COLORS.len() as f32) as usize;
+    let color_idx = (rng.random::<f32>() * FIRE_COLORS.len() as f32) as usize;
     let (r, g, b) = FIRE_COLORS[color_idx];
-    let new_particle = Particle::new(Material::Fire).with_color(r, g, b);
+    let new_particle = old_grid.registry().particle(old_grid.registry().fire()).with_color(r, g, b);
 
     // Handle upward movement
-    if rng.gen::<f32>() < upward_bias {
-        if y > 0 {
-            let dx = if rng.gen::<f32>() < 0.3 {
-                if rng.gen::<bool>() { 1 } else { -1 } // 30% chance to move diagonally
-            } else {
-                0 // 70% chance to move straight up
-            };
-            let new_x = (x as isize + dx) as usize;
-            if new_x > 0 && new_x < config::GRID_WIDTH {
-                new_grid.set_particle(new_x, y - 1, new_particle);
-                return true;
-            }
+    if rng.random::<f32>() < upward_bias && y > 0 {
+        let dx = if rng.random::<f32>() < 0.3 {
+            if rng.random::<bool>() { 1 } else { -1 } // 30% chance to move diagonally
+        } else {
+            0 // 70% chance to move straight up
+        };
+        let new_x = (x as isize + dx) as usize;
+        // Only rise into free cells so flames don't erase what they should ignite
+        if new_x > 0 && new_x < old_grid.width() && old_grid.is_empty(new_x, y - 1) {
+            new_grid.set_particle(new_x, y - 1, new_particle);
+            return true;
         }
     }
 
     // Spread fire with directional probabilities
-    if rng.gen::<f32>() < FIRE_SPREAD_CHANCE {
+    if rng.random::<f32>() < FIRE_SPREAD_CHANCE {
         let spread_directions = [
             (0, -1, 0.5),  // Up: 50%
             (-1, -1, 0.4), // Up-left: 40%
@@ -421,58 +581,21 @@ fn handle_fire(x: usize, y: usize, new_grid: &mut Grid, old_grid: &Grid) -> bool
             let new_x = x as isize + dx;
             let new_y = y as isize + dy;
             
-            if new_x >= 0 && new_x < config::GRID_WIDTH as isize &&
-               new_y >= 0 && new_y < config::GRID_HEIGHT as isize {
+            if new_x >= 0 && new_x < old_grid.width() as isize &&
+               new_y >= 0 && new_y < old_grid.height() as isize {
                 let new_x = new_x as usize;
                 let new_y = new_y as usize;
                 
-                if old_grid.get(new_x, new_y).material_type == Material::Empty as u8 {
-                    if rng.gen::<f32>() < *prob {
-                        new_grid.set_particle(new_x, new_y, new_particle);
-                    }
+                if old_grid.is_empty(new_x, new_y) && rng.random::<f32>() < *prob {
+                    new_grid.set_particle(new_x, new_y, new_particle);
                 }
             }
         }
     }
 
-    // Maintain fire at current position if no movement/spread occurs
+    // Maintain fire at current position if no movement/spread occurs.
+    // Flames never settle, even when they happen to keep their colour.
     new_grid.set_particle(x, y, new_particle);
+    new_grid.wake(x, y);
     true
 }
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
-
@@ -0,0 +1,83 @@
+use bevy::math::Vec2;
+use rand::prelude::*;
+use crate::grid::Grid;
+use crate::materials::types::ParticleFlags;
+use crate::utils::line::bresenham_line;
+
+const EXPLOSION_FIRE_CHANCE: f32 = 0.4; // Chance for a cleared cell to be left burning
+const EXPLOSION_HEAT: f32 = 400.0;      // Temperature added to every cell inside the blast
+const SHOCKWAVE_REACH: f32 = 2.5;       // How far the shockwave reaches, in blast radii
+const EXPLOSION_IMPULSE: f32 = 8.0;     // Speed given to debris right at the edge of the blast
+
+/// A blast centred on a cell, clearing everything within its radius
+#[derive(Clone, Copy, Debug)]
+pub struct Explosion {
+    pub x: usize,
+    pub y: usize,
+    pub radius: f32,
+}
+
+impl Explosion {
+    pub fn new(x: usize, y: usize, radius: f32) -> Self {
+        Self { x, y, radius }
+    }
+}
+
+/// Clears the blast area, leaving fire and heat behind, then throws movable
+/// particles caught in the shockwave outwards
+pub fn explode(grid: &mut Grid, explosion: &Explosion, rng: &mut impl Rng) {
+    let reach = explosion.radius * SHOCKWAVE_REACH;
+    let extent = reach.ceil() as isize;
+    let mut debris = Vec::new();
+
+    for dy in -extent..=extent {
+        for dx in -extent..=extent {
+            let (nx, ny) = (explosion.x as isize + dx, explosion.y as isize + dy);
+            let distance = ((dx * dx + dy * dy) as f32).sqrt();
+            if !grid.in_bounds(nx, ny) || distance > reach {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+
+            if distance <= explosion.radius {
+                if rng.random::<f32>() < EXPLOSION_FIRE_CHANCE {
+                    grid.spawn(nx, ny, grid.registry().fire());
+                } else {
+                    grid.set(nx, ny, grid.registry().empty());
+                }
+                grid.set_velocity(nx, ny, Vec2::ZERO);
+                grid.set_temperature(nx, ny, grid.get_temperature(nx, ny) + EXPLOSION_HEAT);
+            } else if grid.get(nx, ny).flags.contains(ParticleFlags::MOVABLE) {
+                debris.push((nx, ny, Vec2::new(dx as f32, dy as f32)));
+            }
+        }
+    }
+
+    // Move the outermost debris first so the particles behind it have room to follow
+    debris.sort_by(|a, b| b.2.length().total_cmp(&a.2.length()));
+
+    for (x, y, offset) in debris {
+        let distance = offset.length();
+        let falloff = 1.0 - (distance - explosion.radius) / (reach - explosion.radius);
+        let impulse = offset / distance * EXPLOSION_IMPULSE * falloff;
+
+        // Throw the particle along the blast direction until it hits something
+        let target = Vec2::new(x as f32, y as f32) + impulse;
+        let target_x = target.x.round().clamp(0.0, (grid.width() - 1) as f32) as usize;
+        let target_y = target.y.round().clamp(0.0, (grid.height() - 1) as f32) as usize;
+        let mut landing = (x, y);
+        for (px, py) in bresenham_line(x, y, target_x, target_y).skip(1) {
+            if !grid.is_empty(px, py) {
+                break;
+            }
+            landing = (px, py);
+        }
+
+        let velocity = grid.get_velocity(x, y) + impulse;
+        if landing != (x, y) {
+            grid.move_to_with_velocity(x, y, landing.0, landing.1, velocity);
+        } else {
+            grid.set_velocity(x, y, velocity);
+        }
+    }
+}
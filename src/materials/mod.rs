@@ -1,6 +1,11 @@
 mod behavior;
 pub(crate) mod types;
-mod properties;
+pub(crate) mod properties;
+pub(crate) mod thermal;
+pub(crate) mod explosion;
 
-pub use behavior::MaterialBehavior;
-pub use types::Material;
\ No newline at end of file
+pub use behavior::{MaterialBehavior, MAX_REACH};
+pub use types::Material;
+pub use properties::{MaterialProperties, PhaseTransition, Reaction, TransitionCondition};
+pub use thermal::conduct_heat;
+pub use explosion::{explode, Explosion};
@@ -1,45 +1,94 @@
-use super::types::Material;
+use super::types::{Material, ParticleFlags};
 
+pub const MAX_DENSITY: u8 = 31;
+pub const MAX_VISCOSITY: u8 = 15;
+pub const MAX_BLAST_RADIUS: u8 = 32;
+pub const MAX_COLOR_VARIATION: u8 = 64;
+
+/// Physical properties of a material, as loaded into the material registry
 #[derive(Debug, Clone, Copy)]
 pub struct MaterialProperties {
     pub color: (u8, u8, u8),
-    pub density: u8,    // Changed to u8 (0-31)
-    pub viscosity: u8,  // Changed to u8 (0-15)
+    pub color_variation: u8, // 0-64, how far each particle's shade may stray from `color`
+    pub density: u8,    // 0-31, stored as density * 10
+    pub viscosity: u8,  // 0-15, stored as viscosity * 10
+    pub flags: ParticleFlags,
+    pub conductivity: f32,  // 0.0-1.0, share of a temperature difference exchanged per tick
+    pub heat_capacity: f32, // >= 1.0, relative to air; higher values heat up and cool down slower
+    pub temperature: f32,   // temperature when placed, or the held temperature for heat sources
+    pub flammability: f32,  // 0.0-1.0, chance per tick to ignite while touching fire
+    pub fuel: u8,           // ticks a burning particle lasts
+    pub burns_into: Material, // what is left once the fuel runs out
+    pub blast_radius: u8,   // explodes with this radius once burnt out, 0 for no explosion
 }
 
-impl Material {
-    pub fn properties(&self) -> MaterialProperties {
-        match self {
-            Material::Empty => MaterialProperties {
-                color: (0, 0, 0),
-                density: 0,
-                viscosity: 0,
-            },
-            Material::Sand => MaterialProperties {
-                color: (194, 178, 128),
-                density: 16,  // 1.6 * 10
-                viscosity: 9, // 0.9 * 10
-            },
-            Material::Water => MaterialProperties {
-                color: (0, 119, 190),
-                density: 10,  // 1.0 * 10
-                viscosity: 1, // 0.1 * 10
-            },
-            Material::Concrete => MaterialProperties {
-                color: (128, 128, 128),
-                density: 24,  // 2.4 * 10
-                viscosity: 10,// 1.0 * 10
-            },
-            Material::Smoke => MaterialProperties {
-                color: (200, 200, 200),
-                density: 1,   // 0.1 * 10
-                viscosity: 1, // 0.1 * 10
-            },
-            Material::Fire => MaterialProperties {
-                color: (255, 100, 0),  // More orange base color
-                density: 1,   // Very light
-                viscosity: 2, // Slightly more viscous for better shape
-            },
+impl MaterialProperties {
+    /// The colour of a single particle: `color` made lighter or darker by up to
+    /// `color_variation`, plus a slight tint per channel, as picked by the bits of `noise`
+    pub fn shade(&self, noise: u64) -> (u8, u8, u8) {
+        if self.color_variation == 0 {
+            return self.color;
         }
+        // Each roll takes 16 bits of the noise and maps them onto -range..=range
+        let roll = |index: u32, range: i32| ((noise >> (index * 16)) & 0xFFFF) as i32 * (2 * range + 1) / 0x1_0000 - range;
+        let variation = self.color_variation as i32;
+        let brightness = roll(0, variation);
+        let channel = |value: u8, index: u32| (value as i32 + brightness + roll(index, variation / 4)).clamp(0, 255) as u8;
+        (channel(self.color.0, 1), channel(self.color.1, 2), channel(self.color.2, 3))
     }
+
+    /// Whether `shade` can give a particle of this material exactly `color`
+    pub fn is_shade(&self, color: (u8, u8, u8)) -> bool {
+        let variation = self.color_variation as i32;
+        let tint = variation / 4;
+        // The brightness shifts every channel alike, so find the shifts each channel allows and intersect them
+        let (mut lowest, mut highest) = (-variation, variation);
+        for (value, base) in [(color.0, self.color.0), (color.1, self.color.1), (color.2, self.color.2)] {
+            let offset = value as i32 - base as i32;
+            // Channels at the ends of the range may have been clamped from further out
+            if value > 0 {
+                lowest = lowest.max(offset - tint);
+            }
+            if value < 255 {
+                highest = highest.min(offset + tint);
+            }
+        }
+        lowest <= highest
+    }
+}
+
+/// A change into another material, declared per material in the definitions file
+#[derive(Debug, Clone, Copy)]
+pub struct PhaseTransition {
+    pub into: Material,
+    pub condition: TransitionCondition,
+    pub chance: f32, // Probability per tick once the condition holds
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum TransitionCondition {
+    Above(f32),
+    Below(f32),
+    Always,
+}
+
+impl PhaseTransition {
+    pub fn applies_at(&self, temperature: f32) -> bool {
+        match self.condition {
+            TransitionCondition::Above(threshold) => temperature > threshold,
+            TransitionCondition::Below(threshold) => temperature < threshold,
+            TransitionCondition::Always => true,
+        }
+    }
+}
+
+/// A reaction with a touching material, declared per material in the definitions file
+#[derive(Debug, Clone, Copy)]
+pub struct Reaction {
+    pub with: Material,                // The neighbouring material that triggers the reaction
+    pub into: Option<Material>,        // What this cell becomes, if it changes
+    pub other_into: Option<Material>,  // What the neighbouring cell becomes, if it changes
+    pub emit: Option<Material>,        // Spawned into a free cell next to this one
+    pub heat: f32,                     // Temperature added to both cells
+    pub chance: f32,                   // Probability per tick for each touching neighbour
 }
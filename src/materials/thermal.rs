@@ -0,0 +1,146 @@
+use std::ops::Range;
+use crate::config::CHUNK_SIZE;
+use crate::grid::Grid;
+use crate::materials::types::ParticleFlags;
+
+pub const AMBIENT_TEMPERATURE: f32 = 20.0;
+const CONDUCTION_RATE: f32 = 0.2;        // Scales material conductivity into a per-tick exchange
+const AMBIENT_COOLING_RATE: f32 = 0.002; // Share of the difference to ambient lost per tick
+const SETTLED_CHANGE: f32 = 0.001;       // Change per tick below which a cell's temperature counts as settled
+
+/// Buffers reused by every call to `conduct_heat`, kept on the grid so ticks don't allocate
+#[derive(Default)]
+pub struct HeatScratch {
+    spans: Vec<(usize, Range<usize>)>, // Row and columns of the cells heat is conducted through
+    chunk_columns: Vec<Vec<Range<usize>>>, // Columns conducted through by each row of chunks
+    row_columns: Vec<Range<usize>>,
+    before: Vec<f32>, // Temperatures from before this step, for the spanned cells and their neighbours
+    after: Vec<(f32, bool)>, // New temperature of each spanned cell, and whether it allows a transition
+}
+
+/// Exchanges heat between orthogonal neighbours and lets every cell drift back to ambient.
+/// Cells flagged as heat sources never drop below their material's temperature.
+/// Cells whose temperature allows one of their transitions wake their chunk so it gets rolled.
+///
+/// Only chunks simulated this tick or whose temperatures were still settling on the last
+/// one are conducted through, grown by a cell so heat also flows across their borders.
+pub fn conduct_heat(grid: &mut Grid) {
+    let mut scratch = std::mem::take(&mut grid.heat_scratch);
+    find_spans(grid, &mut scratch);
+    compute_temperatures(grid, &mut scratch);
+
+    let (width, chunks_x) = (grid.width(), grid.chunks_x());
+    grid.warm_chunks.fill(false);
+    let cells = scratch.spans.iter().flat_map(|(y, xs)| xs.clone().map(move |x| (x, *y)));
+    for ((x, y), &(temperature, transition)) in cells.zip(&scratch.after) {
+        if (temperature - scratch.before[y * width + x]).abs() > SETTLED_CHANGE {
+            grid.warm_chunks[(y / CHUNK_SIZE) * chunks_x + x / CHUNK_SIZE] = true;
+        }
+        grid.set_temperature(x, y, temperature);
+        if transition {
+            grid.wake(x, y);
+        }
+    }
+    grid.heat_scratch = scratch;
+}
+
+/// Lists the cells heat is conducted through this tick as row spans, each cell once
+fn find_spans(grid: &Grid, scratch: &mut HeatScratch) {
+    let (width, height, chunks_x, chunks_y) = (grid.width(), grid.height(), grid.chunks_x(), grid.chunks_y());
+    let HeatScratch { spans, chunk_columns, row_columns, .. } = scratch;
+
+    chunk_columns.resize_with(chunks_y, Vec::new);
+    for (cy, columns) in chunk_columns.iter_mut().enumerate() {
+        columns.clear();
+        for cx in 0..chunks_x {
+            if grid.is_chunk_active(cx, cy) || grid.warm_chunks[cy * chunks_x + cx] {
+                let (xs, _) = grid.chunk_bounds(cx, cy);
+                push_merged(columns, xs.start.saturating_sub(1)..(xs.end + 1).min(width));
+            }
+        }
+    }
+
+    // Rows along the edge of a row of chunks are also covered by the border of the next one
+    spans.clear();
+    for y in 0..height {
+        let cy = y / CHUNK_SIZE;
+        row_columns.clear();
+        row_columns.extend(chunk_columns[cy].iter().cloned());
+        if y % CHUNK_SIZE == 0 && cy > 0 {
+            row_columns.extend(chunk_columns[cy - 1].iter().cloned());
+        }
+        if (y + 1) % CHUNK_SIZE == 0 && cy + 1 < chunks_y {
+            row_columns.extend(chunk_columns[cy + 1].iter().cloned());
+        }
+        row_columns.sort_unstable_by_key(|columns| columns.start);
+
+        let first = spans.len();
+        for columns in row_columns.drain(..) {
+            match spans[first..].last_mut() {
+                Some((_, last)) if columns.start <= last.end => last.end = last.end.max(columns.end),
+                _ => spans.push((y, columns)),
+            }
+        }
+    }
+}
+
+/// Appends `columns` to a list sorted by start, merging it into the last entry if they touch
+fn push_merged(list: &mut Vec<Range<usize>>, columns: Range<usize>) {
+    match list.last_mut() {
+        Some(last) if columns.start <= last.end => last.end = last.end.max(columns.end),
+        _ => list.push(columns),
+    }
+}
+
+/// Works out the new temperature of every spanned cell from the current ones
+fn compute_temperatures(grid: &Grid, scratch: &mut HeatScratch) {
+    let (width, height) = (grid.width(), grid.height());
+    let HeatScratch { spans, before, after, .. } = scratch;
+
+    // Every cell is updated from its neighbours' temperatures before any of them changes
+    before.resize(width * height, 0.0);
+    for (y, xs) in spans.iter() {
+        let wide = xs.start.saturating_sub(1)..(xs.end + 1).min(width);
+        let rows = [Some((*y, wide)), y.checked_sub(1).map(|up| (up, xs.clone())), (y + 1 < height).then(|| (y + 1, xs.clone()))];
+        for (row, columns) in rows.into_iter().flatten() {
+            let cells = row * width + columns.start..row * width + columns.end;
+            before[cells.clone()].copy_from_slice(&grid.temperatures[cells]);
+        }
+    }
+
+    let registry = grid.registry();
+    let properties = |idx: usize| registry.properties(grid.particles[idx].material());
+    after.clear();
+    for (y, xs) in spans.iter() {
+        let y = *y;
+        for x in xs.clone() {
+            let idx = y * width + x;
+            let props = properties(idx);
+            let temperature = before[idx];
+
+            let neighbours = [
+                (x > 0).then(|| idx - 1),
+                (x < width - 1).then(|| idx + 1),
+                (y > 0).then(|| idx - width),
+                (y < height - 1).then(|| idx + width),
+            ];
+
+            // Heat flows through the worse conductor of each pair
+            let mut heat = 0.0;
+            for neighbour in neighbours.into_iter().flatten() {
+                let conductivity = props.conductivity.min(properties(neighbour).conductivity);
+                heat += CONDUCTION_RATE * conductivity * (before[neighbour] - temperature);
+            }
+            heat += AMBIENT_COOLING_RATE * (AMBIENT_TEMPERATURE - temperature);
+
+            let mut new_temperature = temperature + heat / props.heat_capacity;
+            if props.flags.contains(ParticleFlags::HEAT_SOURCE) {
+                new_temperature = new_temperature.max(props.temperature);
+            }
+
+            let material = grid.particles[idx].material();
+            let transition = registry.transitions(material).iter().any(|transition| transition.applies_at(new_temperature));
+            after.push((new_temperature, transition));
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
+use bevy::prelude::*;
+use bevy::window::PrimaryWindow;
+use bevy_egui::EguiContexts;
+use crate::config::CAMERA_ZOOM_STEP;
+use crate::systems::zoom_camera;
+use crate::utils::grid_utils::window_to_world;
+
+use super::input::PanAnchor;
+use super::resources::BrushSize;
+
+const BRUSH_SIZE_SCROLL_STEP: u8 = 1;
+
+/// Scrolling zooms the view around the cursor, or changes the brush size while Shift is held
+pub fn mouse_wheel_zoom(
+    mut scroll_evr: EventReader<MouseWheel>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut egui_context: EguiContexts,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut brush_size: ResMut<BrushSize>,
+    mut camera_q: Query<(&Camera, &GlobalTransform, &mut Transform, &mut OrthographicProjection)>,
+) {
+    let resize_brush = keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]);
+    // Handle scroll events in batch. Some platforms turn Shift+wheel into horizontal scrolling
+    let scroll_delta: f32 = scroll_evr.read().map(|ev| {
+        let lines = if resize_brush { ev.y + ev.x } else { ev.y };
+        match ev.unit {
+            MouseScrollUnit::Line => lines,
+            MouseScrollUnit::Pixel => lines / 20.0,
+        }
+    }).sum();
+
+    // Scrolling the sidebar shouldn't zoom the view behind it
+    if scroll_delta == 0.0 || egui_context.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    if resize_brush {
+        brush_size.0 = ((brush_size.0 as f32 + scroll_delta * BRUSH_SIZE_SCROLL_STEP as f32)
+            .clamp(1.0, 30.0)) as u8;
+        return;
+    }
+
+    let window = window_query.single();
+    let (camera, camera_transform, mut transform, mut projection) = camera_q.single_mut();
+    let Some(anchor) = window.cursor_position().and_then(|pos| window_to_world(camera, camera_transform, pos)) else { return };
+    zoom_camera(&mut projection, &mut transform, CAMERA_ZOOM_STEP.powf(-scroll_delta), anchor);
+}
+
+/// Dragging with the middle button pans the view, keeping the grabbed point under the cursor
+pub fn middle_drag_pan(
+    buttons: Res<ButtonInput<MouseButton>>,
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    mut pan_anchor: ResMut<PanAnchor>,
+    mut camera_q: Query<(&Camera, &GlobalTransform, &mut Transform)>,
+) {
+    if !buttons.pressed(MouseButton::Middle) {
+        pan_anchor.0 = None;
+        return;
+    }
+
+    let window = window_query.single();
+    let (camera, camera_transform, mut transform) = camera_q.single_mut();
+    let Some(cursor_world) = window.cursor_position().and_then(|pos| window_to_world(camera, camera_transform, pos)) else { return };
+
+    if buttons.just_pressed(MouseButton::Middle) {
+        pan_anchor.0 = Some(cursor_world);
+    } else if let Some(anchor) = pan_anchor.0 {
+        let offset = anchor - cursor_world;
+        transform.translation += offset.extend(0.0);
+    }
+}
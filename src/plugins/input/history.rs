@@ -0,0 +1,241 @@
+use std::collections::{HashMap, VecDeque};
+use std::mem::size_of;
+use bevy::prelude::*;
+use crate::config::UNDO_MEMORY_BUDGET;
+use crate::grid::{Cell, Grid};
+
+/// A cell changed by a stroke, with its contents before and after
+#[derive(Clone, Copy)]
+struct CellChange {
+    x: usize,
+    y: usize,
+    before: Cell,
+    after: Cell,
+}
+
+/// The cells changed by one stroke, from mouse down to mouse up
+struct Stroke {
+    changes: Vec<CellChange>,
+}
+
+impl Stroke {
+    /// Memory held by the stroke, counted against the history budget
+    fn size(&self) -> usize {
+        size_of::<Self>() + self.changes.capacity() * size_of::<CellChange>()
+    }
+}
+
+/// Undo and redo stacks of brush strokes, each stored as a diff of the cells
+/// it changed. The oldest strokes are forgotten once the stacks outgrow the
+/// memory budget.
+#[derive(Resource)]
+pub struct History {
+    grid_id: Option<u64>, // The grid the strokes were recorded on
+    undo: VecDeque<Stroke>,
+    redo: Vec<Stroke>,
+    bytes: usize,
+    budget: usize,
+    pending: Option<HashMap<(usize, usize), Cell>>, // Cells touched by the stroke in progress, as they were before it
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(UNDO_MEMORY_BUDGET)
+    }
+}
+
+impl History {
+    /// Creates an empty history keeping at most `budget` bytes of strokes
+    pub fn new(budget: usize) -> Self {
+        Self {
+            grid_id: None,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            bytes: 0,
+            budget,
+            pending: None,
+        }
+    }
+
+    /// Starts recording a stroke
+    pub fn begin_stroke(&mut self, grid: &Grid) {
+        self.sync(grid);
+        self.pending = Some(HashMap::new());
+    }
+
+    /// Remembers the cell at (x, y) as it was before the current stroke first touched it.
+    /// Must be called before the stroke writes to the cell.
+    pub fn touch(&mut self, grid: &Grid, x: usize, y: usize) {
+        if let Some(pending) = &mut self.pending {
+            pending.entry((x, y)).or_insert_with(|| grid.cell(x, y));
+        }
+    }
+
+    /// Finishes the current stroke, recording the cells it left changed
+    pub fn end_stroke(&mut self, grid: &Grid) {
+        let Some(pending) = self.pending.take() else { return };
+        if self.grid_id != Some(grid.id()) {
+            // The grid was replaced mid-stroke, so the diff describes cells that are gone
+            self.sync(grid);
+            return;
+        }
+
+        let mut changes: Vec<CellChange> = pending
+            .into_iter()
+            .filter_map(|((x, y), before)| {
+                let after = grid.cell(x, y);
+                (after != before).then_some(CellChange { x, y, before, after })
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        changes.shrink_to_fit();
+
+        // A new stroke branches off the history, so whatever was undone can't be redone anymore
+        for stroke in self.redo.drain(..) {
+            self.bytes -= stroke.size();
+        }
+        let stroke = Stroke { changes };
+        self.bytes += stroke.size();
+        self.undo.push_back(stroke);
+
+        while self.bytes > self.budget {
+            let Some(oldest) = self.undo.pop_front() else { break };
+            self.bytes -= oldest.size();
+        }
+    }
+
+    /// Restores the cells changed by the most recent stroke
+    pub fn undo(&mut self, grid: &mut Grid) {
+        self.sync(grid);
+        let Some(stroke) = self.undo.pop_back() else { return };
+        for change in &stroke.changes {
+            grid.set_cell(change.x, change.y, change.before);
+        }
+        self.redo.push(stroke);
+    }
+
+    /// Reapplies the most recently undone stroke
+    pub fn redo(&mut self, grid: &mut Grid) {
+        self.sync(grid);
+        let Some(stroke) = self.redo.pop() else { return };
+        for change in &stroke.changes {
+            grid.set_cell(change.x, change.y, change.after);
+        }
+        self.undo.push_back(stroke);
+    }
+
+    /// Forgets every stroke once the grid was replaced (loaded, imported...), as they no longer apply to it
+    fn sync(&mut self, grid: &Grid) {
+        if self.grid_id != Some(grid.id()) {
+            self.grid_id = Some(grid.id());
+            self.undo.clear();
+            self.redo.clear();
+            self.bytes = 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::materials::Material;
+    use crate::registry::MaterialRegistry;
+
+    // Records one stroke spawning `material` into `cells`
+    fn paint(history: &mut History, grid: &mut Grid, cells: &[(usize, usize)], material: Material) {
+        history.begin_stroke(grid);
+        for &(x, y) in cells {
+            history.touch(grid, x, y);
+            grid.spawn(x, y, material);
+        }
+        history.end_stroke(grid);
+    }
+
+    fn grid() -> Grid {
+        Grid::new(MaterialRegistry::builtin(), 32, 32)
+    }
+
+    #[test]
+    fn undo_restores_cells_and_redo_reapplies_them() {
+        let mut grid = grid();
+        let (empty, sand) = (grid.registry().empty(), grid.registry().sand());
+        let wood = grid.registry().find("Wood").unwrap();
+        let mut history = History::default();
+        paint(&mut history, &mut grid, &[(1, 1), (2, 1)], sand);
+        let painted = (grid.cell(1, 1), grid.cell(2, 1));
+        paint(&mut history, &mut grid, &[(2, 1), (3, 1)], wood);
+
+        history.undo(&mut grid);
+        assert_eq!((grid.cell(1, 1), grid.cell(2, 1)), painted);
+        assert_eq!(grid.get(3, 1).material(), empty);
+        history.undo(&mut grid);
+        assert!([(1, 1), (2, 1), (3, 1)].iter().all(|&(x, y)| grid.get(x, y).material() == empty));
+        // Nothing is left to undo
+        history.undo(&mut grid);
+
+        history.redo(&mut grid);
+        assert_eq!((grid.cell(1, 1), grid.cell(2, 1)), painted);
+        history.redo(&mut grid);
+        assert_eq!([grid.get(2, 1).material(), grid.get(3, 1).material()], [wood, wood]);
+    }
+
+    #[test]
+    fn a_new_stroke_drops_what_was_undone() {
+        let mut grid = grid();
+        let sand = grid.registry().sand();
+        let mut history = History::default();
+        paint(&mut history, &mut grid, &[(1, 1)], sand);
+        history.undo(&mut grid);
+        paint(&mut history, &mut grid, &[(5, 5)], sand);
+
+        history.redo(&mut grid);
+        assert_eq!(grid.get(1, 1).material(), grid.registry().empty());
+    }
+
+    #[test]
+    fn strokes_that_change_nothing_are_not_recorded() {
+        let mut grid = grid();
+        let sand = grid.registry().sand();
+        let mut history = History::default();
+        paint(&mut history, &mut grid, &[(1, 1)], sand);
+        let before = grid.cell(1, 1);
+        history.begin_stroke(&grid);
+        history.touch(&grid, 1, 1);
+        history.end_stroke(&grid);
+
+        // The undo goes back past the empty stroke to the one before it
+        history.undo(&mut grid);
+        assert_ne!(grid.cell(1, 1), before);
+    }
+
+    #[test]
+    fn the_oldest_strokes_are_forgotten_past_the_budget() {
+        let mut grid = grid();
+        let sand = grid.registry().sand();
+        let one_stroke = size_of::<Stroke>() + size_of::<CellChange>();
+        let mut history = History::new(2 * one_stroke);
+        for x in 0..3 {
+            paint(&mut history, &mut grid, &[(x, 0)], sand);
+        }
+
+        for _ in 0..3 {
+            history.undo(&mut grid);
+        }
+        assert_eq!(grid.get(0, 0).material(), sand);
+        assert_eq!(grid.get(1, 0).material(), grid.registry().empty());
+    }
+
+    #[test]
+    fn replacing_the_grid_forgets_its_strokes() {
+        let mut grid = grid();
+        let sand = grid.registry().sand();
+        let mut history = History::default();
+        paint(&mut history, &mut grid, &[(1, 1)], sand);
+
+        let mut loaded = grid.resized(32, 32);
+        history.undo(&mut loaded);
+        assert_eq!(loaded.get(1, 1).material(), sand);
+    }
+}
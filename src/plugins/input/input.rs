@@ -5,4 +5,12 @@ pub struct Drawing(pub bool);
 
 // Resource to store the last mouse grid position
 #[derive(Resource, Default)]
-pub struct LastMouseGridPos(pub Option<(usize, usize)>);
\ No newline at end of file
+pub struct LastMouseGridPos(pub Option<(usize, usize)>);
+
+// Resource to store the corners of the shape being dragged out, from the press to the cursor
+#[derive(Resource, Default)]
+pub struct ShapeDrag(pub Option<((usize, usize), (usize, usize))>);
+
+// Resource to store the world point grabbed when panning with the middle button
+#[derive(Resource, Default)]
+pub struct PanAnchor(pub Option<Vec2>);
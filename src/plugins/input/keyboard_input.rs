@@ -0,0 +1,91 @@
+use bevy::prelude::*;
+use bevy_egui::EguiContexts;
+use crate::grid::Grid;
+use crate::settings::Settings;
+use crate::systems::{fit_camera_to_grid, SimulationControl};
+
+use super::history::History;
+use super::input::Drawing;
+use super::resources::BrushMode;
+
+/// Ctrl+Z undoes the last stroke and Ctrl+Shift+Z redoes it (Cmd on macOS)
+pub fn undo_redo_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut egui_context: EguiContexts,
+    drawing: Res<Drawing>,
+    mut history: ResMut<History>,
+    mut grid: ResMut<Grid>,
+) {
+    if drawing.0 || !keys.just_pressed(KeyCode::KeyZ) {
+        return;
+    }
+    // Text fields in the sidebar handle their own undo
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if !keys.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight, KeyCode::SuperLeft, KeyCode::SuperRight]) {
+        return;
+    }
+
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        history.redo(&mut grid);
+    } else {
+        history.undo(&mut grid);
+    }
+}
+
+/// B, O, E and R switch the brush to paint, overwrite, erase and replace
+pub fn brush_mode_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut egui_context: EguiContexts,
+    mut mode: ResMut<BrushMode>,
+) {
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let shortcuts = [
+        (KeyCode::KeyB, BrushMode::Paint),
+        (KeyCode::KeyO, BrushMode::Overwrite),
+        (KeyCode::KeyE, BrushMode::Erase),
+        (KeyCode::KeyR, BrushMode::Replace),
+    ];
+    for (key, shortcut) in shortcuts {
+        if keys.just_pressed(key) {
+            *mode = shortcut;
+        }
+    }
+}
+
+/// Space pauses and resumes the simulation, period advances it by a single tick
+pub fn simulation_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut egui_context: EguiContexts,
+    mut control: ResMut<SimulationControl>,
+) {
+    if egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    if keys.just_pressed(KeyCode::Space) {
+        control.paused = !control.paused;
+    }
+    if keys.just_pressed(KeyCode::Period) {
+        control.step();
+    }
+}
+
+/// Home zooms and centres the view to show the whole grid
+pub fn view_shortcuts(
+    keys: Res<ButtonInput<KeyCode>>,
+    mut egui_context: EguiContexts,
+    grid: Res<Grid>,
+    settings: Res<Settings>,
+    mut camera_q: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+) {
+    if !keys.just_pressed(KeyCode::Home) || egui_context.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+    let (camera, mut transform, mut projection) = camera_q.single_mut();
+    let Some(viewport_size) = camera.logical_viewport_size() else { return };
+    let grid_size = Vec2::new(grid.width() as f32, grid.height() as f32) * settings.cell_size;
+    fit_camera_to_grid(&mut projection, &mut transform, grid_size, viewport_size, settings.cell_size);
+}
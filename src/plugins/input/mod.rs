@@ -1,8 +1,15 @@
 mod mouse_input;
+mod keyboard_input;
+mod camera_input;
+pub(crate) mod history;
 pub(crate) mod resources;
 
 use bevy::prelude::*;
+use crate::systems::update_grid;
 pub use mouse_input::*;
+pub use keyboard_input::*;
+pub use camera_input::*;
+#[allow(clippy::module_inception)]
 pub mod input;
 
 // Make the struct public
@@ -10,7 +17,19 @@ pub struct InputPlugin;
 
 impl bevy::prelude::Plugin for InputPlugin {
     fn build(&self, app: &mut bevy::prelude::App) {
-        app.add_systems(Update, mouse_click_draw);
+        app
+            .init_resource::<history::History>()
+            .init_resource::<input::PanAnchor>()
+            .add_systems(Update, (
+                // Reads the ticks the simulation ran this frame to time the brush's fling
+                mouse_click_draw.after(update_grid),
+                mouse_wheel_zoom,
+                middle_drag_pan,
+                undo_redo_shortcuts,
+                brush_mode_shortcuts,
+                simulation_shortcuts,
+                view_shortcuts,
+            ));
     }
 }
 
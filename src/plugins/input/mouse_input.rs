@@ -1,114 +1,231 @@
-use bevy::input::mouse::{MouseScrollUnit, MouseWheel};
 use bevy::prelude::*;
 use bevy::window::PrimaryWindow;
-use crate::config;
+use rand::Rng;
 use crate::grid::Grid;
 use crate::utils::{line::bresenham_line, grid_utils::get_grid_pos};
-use crate::materials::Material;
+use crate::utils::shapes::{ellipse_cells, flood_fill_cells, line_cells, rectangle_cells};
+use crate::materials::{Explosion, Material};
+use crate::settings::Settings;
+use crate::systems::SimulationControl;
+use crate::utils::rng::SimulationRng;
 
-use super::input::{Drawing, LastMouseGridPos};
-use super::resources::{BrushSize, SelectedMaterial};
+use super::history::History;
+use super::input::{Drawing, LastMouseGridPos, ShapeDrag};
+use super::resources::{Brush, BrushMode, Tool};
 
-const BRUSH_SIZE_SCROLL_STEP: u8 = 1;
+const BRUSH_FLING_FACTOR: f32 = 0.5; // Share of the cursor speed (cells per tick) given to placed particles
+const BRUSH_MAX_FLING_SPEED: f32 = 8.0;
 
-fn place_material_with_brush(grid: &mut Grid, x: usize, y: usize, material: u8, brush_size: usize) {
-    let half_size = brush_size as isize / 2;
-    let radius_sq = half_size * half_size;
-    
-    // Pre-calculate the radius check results
-    let radius_mask: Vec<Vec<bool>> = (-half_size..=half_size)
-        .map(|dx| (-half_size..=half_size)
-            .map(|dy| dx * dx + dy * dy <= radius_sq)
-            .collect())
-        .collect();
-    
-    // First pass: check if we're placing on top of existing particles
-    let mut should_add_velocity = false;
-    'outer: for (i, dx) in (-half_size..=half_size).enumerate() {
-        for (j, dy) in (-half_size..=half_size).enumerate() {
-            if !radius_mask[i][j] { continue; }
-            
-            let nx = x as isize + dx;
-            let ny = y as isize + dy;
-            if grid.in_bounds(nx, ny) {
-                let ny = ny as usize;
-                if ny < config::GRID_HEIGHT - 1 && 
-                   grid.get(nx as usize, ny + 1).material_type != Material::Empty as u8 {
-                    should_add_velocity = true;
-                    break 'outer;
-                }
+/// Paints with the current brush settings during one frame of a stroke
+struct Painter<'a, 'w> {
+    grid: &'a mut Grid,
+    history: &'a mut History,
+    brush: &'a Brush<'w>,
+    rng: &'a mut SimulationRng,
+    dab: Vec<(isize, isize)>, // Cells covered by one dab of the brush, relative to its centre
+}
+
+impl<'a, 'w> Painter<'a, 'w> {
+    fn new(grid: &'a mut Grid, history: &'a mut History, brush: &'a Brush<'w>, rng: &'a mut SimulationRng) -> Self {
+        let dab = brush.shape.offsets(brush.size.0, brush.stamp.stamp.as_ref());
+        Self { grid, history, brush, rng, dab }
+    }
+
+    /// Writes the brush material into the cell at (x, y) if the brush mode allows it
+    fn paint_cell(&mut self, x: usize, y: usize, velocity: Vec2) {
+        let selected = Material::from_id(self.brush.material.0);
+        let replace_source = Material::from_id(self.brush.replace_source.0);
+        let empty = self.grid.registry().empty();
+        let Some(target) = self.brush.mode.apply(self.grid.get(x, y).material(), selected, replace_source, empty) else { return };
+
+        let velocity = match (target, &*self.brush.pour) {
+            (target, _) if target == empty => Vec2::ZERO,
+            (_, pour) if pour.enabled => velocity + pour.velocity,
+            _ => velocity,
+        };
+        self.history.touch(self.grid, x, y);
+        self.grid.spawn(x, y, target);
+        self.grid.set_fuel(x, y, 0);
+        self.grid.set_velocity(x, y, velocity);
+    }
+
+    /// Paints one dab of the brush centred on (x, y), or a random share of it when spraying
+    fn place_material_with_brush(&mut self, x: usize, y: usize, velocity: Vec2) {
+        // Material placed on top of existing particles gets a downward nudge
+        let on_top = self.dab.iter().any(|&(dx, dy)| {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            self.grid.in_bounds(nx, ny + 1) && !self.grid.is_empty(nx as usize, ny as usize + 1)
+        });
+        let velocity = if on_top { velocity + Vec2::Y } else { velocity };
+
+        for i in 0..self.dab.len() {
+            let (nx, ny) = (x as isize + self.dab[i].0, y as isize + self.dab[i].1);
+            if !self.grid.in_bounds(nx, ny) {
+                continue;
             }
+            if self.brush.spray.enabled && !self.rng.random_ratio(self.brush.spray.density.min(100).into(), 100) {
+                continue;
+            }
+            self.paint_cell(nx as usize, ny as usize, velocity);
         }
     }
 
-    // Second pass: place particles
-    for (i, dx) in (-half_size..=half_size).enumerate() {
-        for (j, dy) in (-half_size..=half_size).enumerate() {
-            if !radius_mask[i][j] { continue; }
-            
-            let nx = x as isize + dx;
-            let ny = y as isize + dy;
-            if grid.in_bounds(nx, ny) {
-                let nx = nx as usize;
-                let ny = ny as usize;
-                if grid.get(nx, ny).material_type == Material::Empty as u8 {
-                    grid.set(nx, ny, Material::from_id(material));
-                    grid.set_velocity(nx, ny, if should_add_velocity { 1.0 } else { 0.0 });
+    /// Draws the shape dragged out from `start` to `end` with the current tool
+    fn draw_shape(&mut self, start: (usize, usize), end: (usize, usize)) {
+        let cells = match *self.brush.tool {
+            Tool::Line => {
+                for (x, y) in line_cells(start, end) {
+                    self.place_material_with_brush(x, y, Vec2::ZERO);
                 }
+                return;
+            }
+            Tool::Rectangle => rectangle_cells(start, end, self.brush.fill_shapes.0),
+            Tool::Ellipse => ellipse_cells(start, end, self.brush.fill_shapes.0),
+            Tool::Brush | Tool::Fill | Tool::Explosion => return,
+        };
+
+        // Filled shapes cover exactly their area, outlines are traced with the brush
+        for (x, y) in cells {
+            if self.brush.fill_shapes.0 {
+                self.paint_cell(x, y, Vec2::ZERO);
+            } else {
+                self.place_material_with_brush(x, y, Vec2::ZERO);
             }
         }
     }
+
+    /// Paints the contiguous region of one material around (x, y).
+    /// Returns why the region was left alone if the brush mode doesn't change it.
+    fn flood_fill(&mut self, x: usize, y: usize) -> Option<String> {
+        let registry = self.grid.registry();
+        let current = self.grid.get(x, y).material();
+        let selected = Material::from_id(self.brush.material.0);
+        let replace_source = Material::from_id(self.brush.replace_source.0);
+        if self.brush.mode.apply(current, selected, replace_source, registry.empty()).is_none() {
+            return Some(match *self.brush.mode {
+                BrushMode::Paint if current != registry.empty() => {
+                    format!("Paint only fills empty space, this region holds {}", registry.name(current))
+                }
+                BrushMode::Replace if current != replace_source => {
+                    format!("Replace only fills {}, this region holds {}", registry.name(replace_source), registry.name(current))
+                }
+                _ => format!("This region already holds {}", registry.name(current)),
+            });
+        }
+
+        for (x, y) in flood_fill_cells(self.grid, x, y) {
+            self.paint_cell(x, y, Vec2::ZERO);
+        }
+        None
+    }
+}
+
+/// Velocity given to material placed during a stroke
+#[derive(Default)]
+pub struct Fling {
+    from: (usize, usize), // Cursor position the last time the simulation ticked
+    velocity: Vec2,
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn mouse_click_draw(
     window_query: Query<&Window, With<PrimaryWindow>>,
     buttons: Res<ButtonInput<MouseButton>>,
-    mut scroll_evr: EventReader<MouseWheel>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
-    selected_material: Res<SelectedMaterial>,
+    mut brush: Brush,
     mut grid: ResMut<Grid>,
     mut drawing: ResMut<Drawing>,
-    mut brush_size: ResMut<BrushSize>,
     mut last_pos: ResMut<LastMouseGridPos>,
+    mut history: ResMut<History>,
+    mut shape_drag: ResMut<ShapeDrag>,
+    mut rng: ResMut<SimulationRng>,
+    control: Res<SimulationControl>,
+    mut fling: Local<Fling>,
+    settings: Res<Settings>,
 ) {
-    // Handle scroll events in batch
-    let scroll_delta: f32 = scroll_evr.read().map(|ev| match ev.unit {
-        MouseScrollUnit::Line => ev.y,
-        MouseScrollUnit::Pixel => ev.y / 20.0,
-    }).sum();
-    
-    if scroll_delta != 0.0 {
-        brush_size.0 = ((brush_size.0 as f32 + scroll_delta * BRUSH_SIZE_SCROLL_STEP as f32)
-            .clamp(1.0, 30.0)) as u8;
-    }
-
     let window = window_query.get_single().unwrap();
     let (camera, camera_transform) = camera_q.single();
 
+    // Right click sets off an explosion whatever the tool, as a shortcut for the explosion tool
+    let explodes = buttons.just_pressed(MouseButton::Right)
+        || (*brush.tool == Tool::Explosion && buttons.just_pressed(MouseButton::Left) && !drawing.0);
+    if explodes {
+        if let Some((x, y)) = get_grid_pos(window, camera, camera_transform, &grid, settings.cell_size) {
+            grid.queue_explosion(Explosion::new(x, y, brush.explosion_radius.0 as f32));
+        }
+    }
+
     // Only calculate grid position if needed
     if !buttons.pressed(MouseButton::Left) {
+        if drawing.0 {
+            // Shapes are only drawn once the button is released
+            if let Some((start, end)) = shape_drag.0.take() {
+                Painter::new(&mut grid, &mut history, &brush, &mut rng).draw_shape(start, end);
+            }
+            history.end_stroke(&grid);
+        }
         drawing.0 = false;
         last_pos.0 = None;
         return;
     }
 
-    let Some(current_pos) = get_grid_pos(window, camera, camera_transform) else { return };
+    let Some(current_pos) = get_grid_pos(window, camera, camera_transform, &grid, settings.cell_size) else { return };
+
+    if brush.tool.is_shape() {
+        match &mut shape_drag.0 {
+            Some((_, end)) => *end = current_pos,
+            None if !drawing.0 => {
+                drawing.0 = true;
+                history.begin_stroke(&grid);
+                shape_drag.0 = Some((current_pos, current_pos));
+            }
+            None => {}
+        }
+        return;
+    }
+
+    // Explosions go off on the click alone, there is nothing to drag
+    if *brush.tool == Tool::Explosion {
+        return;
+    }
+
+    if *brush.tool == Tool::Fill {
+        if !drawing.0 {
+            drawing.0 = true;
+            history.begin_stroke(&grid);
+            let left_alone = Painter::new(&mut grid, &mut history, &brush, &mut rng).flood_fill(current_pos.0, current_pos.1);
+            brush.fill_status.0 = left_alone.map(Ok);
+        }
+        return;
+    }
 
     if !drawing.0 {
         // First click
         drawing.0 = true;
-        place_material_with_brush(&mut grid, current_pos.0, current_pos.1, selected_material.0, brush_size.0.into());
+        history.begin_stroke(&grid);
+        Painter::new(&mut grid, &mut history, &brush, &mut rng).place_material_with_brush(current_pos.0, current_pos.1, Vec2::ZERO);
         last_pos.0 = Some(current_pos);
+        *fling = Fling { from: current_pos, velocity: Vec2::ZERO };
         return;
     }
 
-    // Continuous drawing
-    place_material_with_brush(&mut grid, current_pos.0, current_pos.1, selected_material.0, brush_size.0.into());
+    // Continuous drawing flings material along with the cursor, at the speed it moved
+    // over the ticks run this frame. Frames without a tick keep the last speed.
+    let ticks = control.ticks_run();
+    if ticks > 0 {
+        let delta = Vec2::new(current_pos.0 as f32 - fling.from.0 as f32, current_pos.1 as f32 - fling.from.1 as f32);
+        fling.velocity = (delta / ticks as f32 * BRUSH_FLING_FACTOR).clamp_length_max(BRUSH_MAX_FLING_SPEED);
+        fling.from = current_pos;
+    }
+    let velocity = fling.velocity;
+    let mut painter = Painter::new(&mut grid, &mut history, &brush, &mut rng);
+    painter.place_material_with_brush(current_pos.0, current_pos.1, velocity);
     
-    if let Some(last) = last_pos.0 {
+    // Spraying only dabs at the cursor, so holding still keeps building up material
+    if let (Some(last), false) = (last_pos.0, brush.spray.enabled) {
         // Use itertools for more efficient iteration
         for (x, y) in bresenham_line(last.0, last.1, current_pos.0, current_pos.1) {
-            place_material_with_brush(&mut grid, x, y, selected_material.0, brush_size.0.into());
+            painter.place_material_with_brush(x, y, velocity);
         }
     }
     last_pos.0 = Some(current_pos);
@@ -1,7 +1,210 @@
+use bevy::ecs::system::SystemParam;
 use bevy::prelude::*;
+use crate::config::DEFAULT_STAMP_PATH;
+use crate::image_io::Stamp;
+use crate::materials::Material;
 
 #[derive(Resource, Default)]
 pub struct BrushSize(pub u8);
 
 #[derive(Resource, Default)]
 pub struct SelectedMaterial(pub u8);
+
+/// The footprint of one brush dab
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BrushShape {
+    #[default]
+    Circle,
+    Square,
+    Diamond,
+    /// The opaque pixels of the `BrushStamp` bitmap
+    Stamp,
+}
+
+impl BrushShape {
+    pub const ALL: [BrushShape; 4] = [BrushShape::Circle, BrushShape::Square, BrushShape::Diamond, BrushShape::Stamp];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BrushShape::Circle => "Circle",
+            BrushShape::Square => "Square",
+            BrushShape::Diamond => "Diamond",
+            BrushShape::Stamp => "Stamp",
+        }
+    }
+
+    /// Offsets of the cells a dab of `size` covers, relative to the cursor.
+    /// Falls back to a circle while no stamp is loaded.
+    pub fn offsets(self, size: u8, stamp: Option<&Stamp>) -> Vec<(isize, isize)> {
+        if let (BrushShape::Stamp, Some(stamp)) = (self, stamp) {
+            return stamp.offsets(size.into());
+        }
+
+        let half_size = size as isize / 2;
+        let mut offsets = Vec::new();
+        for dy in -half_size..=half_size {
+            for dx in -half_size..=half_size {
+                let covered = match self {
+                    BrushShape::Square => true,
+                    BrushShape::Diamond => dx.abs() + dy.abs() <= half_size,
+                    BrushShape::Circle | BrushShape::Stamp => dx * dx + dy * dy <= half_size * half_size,
+                };
+                if covered {
+                    offsets.push((dx, dy));
+                }
+            }
+        }
+        offsets
+    }
+}
+
+/// The bitmap used by the `Stamp` brush shape, loaded from the sidebar
+#[derive(Resource)]
+pub struct BrushStamp {
+    pub path: String,
+    pub stamp: Option<Stamp>,
+    pub status: Option<Result<String, String>>,
+}
+
+impl Default for BrushStamp {
+    fn default() -> Self {
+        Self { path: DEFAULT_STAMP_PATH.to_string(), stamp: None, status: None }
+    }
+}
+
+/// Spray painting: each cell under the brush is only painted with a chance of `density` percent
+#[derive(Resource)]
+pub struct Spray {
+    pub enabled: bool,
+    pub density: u8,
+}
+
+impl Default for Spray {
+    fn default() -> Self {
+        Self { enabled: false, density: 20 }
+    }
+}
+
+/// A velocity given to painted particles (in cells per tick, positive y pointing down),
+/// so poured material keeps moving
+#[derive(Resource, Default)]
+pub struct PourVelocity {
+    pub enabled: bool,
+    pub velocity: Vec2,
+}
+
+/// Which cells the brush writes to, and what it writes
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum BrushMode {
+    /// Paints the selected material into empty cells only
+    #[default]
+    Paint,
+    /// Paints the selected material over anything
+    Overwrite,
+    /// Clears cells back to empty
+    Erase,
+    /// Paints the selected material over cells of the `ReplaceMaterial` only
+    Replace,
+}
+
+impl BrushMode {
+    pub const ALL: [BrushMode; 4] = [BrushMode::Paint, BrushMode::Overwrite, BrushMode::Erase, BrushMode::Replace];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BrushMode::Paint => "Paint",
+            BrushMode::Overwrite => "Overwrite",
+            BrushMode::Erase => "Erase",
+            BrushMode::Replace => "Replace",
+        }
+    }
+
+    /// The material the brush writes into a cell currently holding `current`,
+    /// or None when the cell is left alone
+    pub fn apply(self, current: Material, selected: Material, replace_source: Material, empty: Material) -> Option<Material> {
+        let target = match self {
+            BrushMode::Paint => (current == empty).then_some(selected)?,
+            BrushMode::Overwrite => selected,
+            BrushMode::Erase => empty,
+            BrushMode::Replace => (current == replace_source).then_some(selected)?,
+        };
+        (target != current).then_some(target)
+    }
+}
+
+/// The material the `Replace` brush mode paints over
+#[derive(Resource, Default)]
+pub struct ReplaceMaterial(pub u8);
+
+/// What a left-click drag draws
+#[derive(Resource, Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Tool {
+    /// Freehand painting with the brush
+    #[default]
+    Brush,
+    /// A straight line from the press to the release, as thick as the brush
+    Line,
+    /// A rectangle spanned by the press and the release
+    Rectangle,
+    /// An ellipse inscribed in the rectangle spanned by the press and the release
+    Ellipse,
+    /// Paints the contiguous region of one material under the cursor
+    Fill,
+    /// Sets off an explosion of `ExplosionRadius` under the cursor on every click
+    Explosion,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 6] = [Tool::Brush, Tool::Line, Tool::Rectangle, Tool::Ellipse, Tool::Fill, Tool::Explosion];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Brush => "Brush",
+            Tool::Line => "Line",
+            Tool::Rectangle => "Rectangle",
+            Tool::Ellipse => "Ellipse",
+            Tool::Fill => "Fill",
+            Tool::Explosion => "Explosion",
+        }
+    }
+
+    /// Whether the tool drags out a shape that is only drawn on release
+    pub fn is_shape(self) -> bool {
+        matches!(self, Tool::Line | Tool::Rectangle | Tool::Ellipse)
+    }
+}
+
+/// Radius in cells of the explosions set off by the explosion tool and right-clicks
+#[derive(Resource)]
+pub struct ExplosionRadius(pub u8);
+
+impl Default for ExplosionRadius {
+    fn default() -> Self {
+        Self(8)
+    }
+}
+
+/// Whether rectangles and ellipses are filled, or only outlined with the brush
+#[derive(Resource, Default)]
+pub struct FillShapes(pub bool);
+
+/// Why the last use of the fill tool left its region alone, shown under the tool picker
+#[derive(Resource, Default)]
+pub struct FillStatus(pub Option<Result<String, String>>);
+
+/// The brush settings shared by the drawing systems and the sidebar
+#[derive(SystemParam)]
+pub struct Brush<'w> {
+    pub material: ResMut<'w, SelectedMaterial>,
+    pub size: ResMut<'w, BrushSize>,
+    pub mode: ResMut<'w, BrushMode>,
+    pub replace_source: ResMut<'w, ReplaceMaterial>,
+    pub tool: ResMut<'w, Tool>,
+    pub fill_shapes: ResMut<'w, FillShapes>,
+    pub fill_status: ResMut<'w, FillStatus>,
+    pub explosion_radius: ResMut<'w, ExplosionRadius>,
+    pub shape: ResMut<'w, BrushShape>,
+    pub stamp: ResMut<'w, BrushStamp>,
+    pub spray: ResMut<'w, Spray>,
+    pub pour: ResMut<'w, PourVelocity>,
+}
@@ -1,24 +1,76 @@
 use bevy::prelude::*;
+use bevy::render::{Render, RenderApp, RenderSet};
+use crate::config::{MATERIALS_PATH, SIMULATION_SEED};
 use crate::grid::Grid;
-use crate::systems::{setup, update_grid, render_grid};
+use crate::registry::MaterialRegistry;
+use crate::settings::Settings;
+use crate::systems::{
+    setup, update_grid, render_grid, extract_texture_update, write_texture_regions, fit_camera_viewport, fit_view_to_grid,
+    record_frame, finish_recording_on_exit, DebugView, ParallelUpdate, PendingTextureWrites, Recording, SidebarWidth,
+    SimulationControl, TextureUpdate, TickTime,
+};
+use crate::utils::rng::SimulationRng;
 
-use super::input::input::{Drawing, LastMouseGridPos};
-use super::input::resources::{BrushSize, SelectedMaterial};
+use super::input::input::{Drawing, LastMouseGridPos, ShapeDrag};
+use super::input::resources::{
+    BrushMode, BrushShape, BrushSize, BrushStamp, ExplosionRadius, FillShapes, FillStatus, PourVelocity, ReplaceMaterial, SelectedMaterial, Spray, Tool,
+};
 
 pub struct SimulationPlugin;
 
 impl Plugin for SimulationPlugin {
     fn build(&self, app: &mut App) {
+        let registry = MaterialRegistry::load_or_builtin(MATERIALS_PATH);
+        let settings = app.world().resource::<Settings>();
+        let grid = Grid::new(registry.clone(), settings.width, settings.height);
+        let control = SimulationControl::new(settings.ticks_per_second);
+        let sand = registry.sand().id();
+        let rng = match SIMULATION_SEED {
+            Some(seed) => SimulationRng::new(seed),
+            None => SimulationRng::from_entropy(),
+        };
+
         app
-            .insert_resource(Grid::new())
-            .insert_resource(SelectedMaterial(1))
+            .insert_resource(grid)
+            .insert_resource(registry)
+            .insert_resource(rng)
+            .insert_resource(SelectedMaterial(sand))
             .insert_resource(BrushSize(3))
+            .insert_resource(BrushShape::default())
+            .init_resource::<BrushStamp>()
+            .init_resource::<Spray>()
+            .init_resource::<PourVelocity>()
+            .insert_resource(BrushMode::default())
+            .insert_resource(ReplaceMaterial(sand))
+            .insert_resource(Tool::default())
+            .insert_resource(FillShapes(true))
+            .init_resource::<FillStatus>()
+            .init_resource::<ExplosionRadius>()
+            .insert_resource(ParallelUpdate(true))
+            .insert_resource(TickTime::default())
+            .insert_resource(control)
+            .init_resource::<Recording>()
             .insert_resource(LastMouseGridPos::default())
             .insert_resource(Drawing::default())
+            .insert_resource(ShapeDrag::default())
+            .init_resource::<SidebarWidth>()
+            .init_resource::<TextureUpdate>()
+            .init_resource::<DebugView>()
             .add_systems(Startup, setup)
             .add_systems(Update, (
                 update_grid,
+                (fit_camera_viewport, fit_view_to_grid).chain().before(render_grid),
                 render_grid,
-            ));
+                record_frame.after(update_grid),
+            ))
+            .add_systems(Last, finish_recording_on_exit);
+
+        // Changed regions of the grid are written into the GPU texture directly
+        if let Some(render_app) = app.get_sub_app_mut(RenderApp) {
+            render_app
+                .init_resource::<PendingTextureWrites>()
+                .add_systems(ExtractSchedule, extract_texture_update)
+                .add_systems(Render, write_texture_regions.in_set(RenderSet::PrepareResources));
+        }
     }
 }
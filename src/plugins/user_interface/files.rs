@@ -0,0 +1,184 @@
+use bevy::ecs::system::SystemParam;
+use bevy::prelude::*;
+use bevy_egui::egui;
+use crate::config::{DEFAULT_EXPORT_PATH, DEFAULT_IMPORT_PATH, DEFAULT_SAVE_PATH, PALETTE_PATH};
+use crate::grid::Grid;
+use crate::image_io::{export_png, import_png, ExportScale, FitMode, Palette, RecordFormat};
+use crate::registry::MaterialRegistry;
+use crate::save::{load_grid, save_grid};
+use crate::systems::Recording;
+
+/// The save file picked in the sidebar and the outcome of the last action on it
+#[derive(Resource)]
+pub struct SaveFile {
+    pub path: String,
+    pub status: Option<Result<String, String>>,
+}
+
+impl Default for SaveFile {
+    fn default() -> Self {
+        Self { path: DEFAULT_SAVE_PATH.to_string(), status: None }
+    }
+}
+
+/// The image picked for import in the sidebar and the outcome of the last import
+#[derive(Resource)]
+pub struct ImportFile {
+    pub path: String,
+    pub fit: FitMode,
+    pub status: Option<Result<String, String>>,
+}
+
+impl Default for ImportFile {
+    fn default() -> Self {
+        Self { path: DEFAULT_IMPORT_PATH.to_string(), fit: FitMode::default(), status: None }
+    }
+}
+
+/// The PNG screenshots are exported to from the sidebar and the outcome of the last export
+#[derive(Resource)]
+pub struct ExportFile {
+    pub path: String,
+    pub scale: ExportScale,
+    pub status: Option<Result<String, String>>,
+}
+
+impl Default for ExportFile {
+    fn default() -> Self {
+        Self { path: DEFAULT_EXPORT_PATH.to_string(), scale: ExportScale::default(), status: None }
+    }
+}
+
+/// The file and recording settings edited in the sidebar
+#[derive(SystemParam)]
+pub struct Files<'w> {
+    pub save: ResMut<'w, SaveFile>,
+    pub import: ResMut<'w, ImportFile>,
+    pub export: ResMut<'w, ExportFile>,
+    pub recording: ResMut<'w, Recording>,
+}
+
+/// Sidebar section for saving the grid to and loading it from a file
+pub fn file_controls(ui: &mut egui::Ui, grid: &mut Grid, registry: &MaterialRegistry, save_file: &mut SaveFile) {
+    ui.label("Save File:");
+    ui.text_edit_singleline(&mut save_file.path);
+
+    ui.horizontal(|ui| {
+        if ui.button("Save").clicked() {
+            save_file.status = Some(match save_grid(grid, &save_file.path) {
+                Ok(()) => Ok(format!("Saved to {}", save_file.path)),
+                Err(err) => Err(format!("Save failed: {err}")),
+            });
+        }
+        if ui.button("Load").clicked() {
+            save_file.status = Some(match load_grid(&save_file.path, registry) {
+                Ok(loaded) => {
+                    // Saves keep the size they were made at, which may not be the current one
+                    let (from, to) = ((grid.width(), grid.height()), (loaded.width(), loaded.height()));
+                    *grid = loaded;
+                    if from == to {
+                        Ok(format!("Loaded {}", save_file.path))
+                    } else {
+                        Ok(format!(
+                            "Loaded {}, resizing the world from {}x{} to {}x{}",
+                            save_file.path, from.0, from.1, to.0, to.1
+                        ))
+                    }
+                }
+                Err(err) => Err(format!("Load failed: {err}")),
+            });
+        }
+    });
+
+    status_label(ui, &save_file.status);
+}
+
+/// Sidebar section for replacing the grid with a PNG level layout
+pub fn import_controls(ui: &mut egui::Ui, grid: &mut Grid, registry: &MaterialRegistry, import_file: &mut ImportFile) {
+    ui.label("Import PNG:");
+    ui.text_edit_singleline(&mut import_file.path);
+
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut import_file.fit, FitMode::Resize, "Resize");
+        ui.radio_value(&mut import_file.fit, FitMode::Crop, "Crop");
+        if ui.button("Import").clicked() {
+            // Reload the palette every time so edits apply without a restart
+            let imported = Palette::load_or_registry(PALETTE_PATH, registry)
+                .and_then(|palette| {
+                    import_png(&import_file.path, registry, &palette, import_file.fit, grid.width(), grid.height())
+                });
+            import_file.status = Some(match imported {
+                Ok((imported, report)) => {
+                    *grid = imported;
+                    Ok(format!("Imported {} pixels, {} approximated", report.pixels, report.approximated))
+                }
+                Err(err) => Err(format!("Import failed: {err}")),
+            });
+        }
+    });
+
+    status_label(ui, &import_file.status);
+}
+
+/// Sidebar section for writing the grid's colours to a PNG, with cells drawn `cell_size` pixels wide on screen
+pub fn export_controls(ui: &mut egui::Ui, grid: &Grid, cell_size: f32, export_file: &mut ExportFile) {
+    ui.label("Export PNG:");
+    ui.text_edit_singleline(&mut export_file.path);
+
+    ui.horizontal(|ui| {
+        ui.radio_value(&mut export_file.scale, ExportScale::Native, "1x");
+        ui.radio_value(&mut export_file.scale, ExportScale::Screen, format!("{}x", ExportScale::Screen.factor(cell_size)));
+        if ui.button("Export").clicked() {
+            export_file.status = Some(match export_png(grid, &export_file.path, export_file.scale.factor(cell_size)) {
+                Ok(()) => Ok(format!("Exported to {}", export_file.path)),
+                Err(err) => Err(format!("Export failed: {err}")),
+            });
+        }
+    });
+
+    status_label(ui, &export_file.status);
+}
+
+/// Sidebar section for recording the simulation to a GIF or PNG frames
+pub fn recording_controls(ui: &mut egui::Ui, cell_size: f32, recording: &mut Recording) {
+    ui.label("Recording:");
+    ui.add_enabled_ui(!recording.is_recording(), |ui| {
+        ui.text_edit_singleline(&mut recording.path);
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut recording.format, RecordFormat::Gif, "GIF");
+            ui.radio_value(&mut recording.format, RecordFormat::PngSequence, "PNG frames");
+        });
+        ui.horizontal(|ui| {
+            ui.radio_value(&mut recording.scale, ExportScale::Native, "1x");
+            ui.radio_value(&mut recording.scale, ExportScale::Screen, format!("{}x", ExportScale::Screen.factor(cell_size)));
+        });
+        ui.add(egui::Slider::new(&mut recording.frame_skip, 0..=10).text("Frame Skip"));
+        ui.add(egui::Slider::new(&mut recording.max_seconds, 1..=120).text("Max Seconds"));
+    });
+
+    if let Some((frames, dropped, elapsed)) = recording.progress() {
+        if ui.button("Stop Recording").clicked() {
+            recording.stop();
+        }
+        ui.label(egui::RichText::new(format!("Recording: {frames} frames, {:.1} s", elapsed.as_secs_f32())).small());
+        if dropped > 0 {
+            ui.label(egui::RichText::new(format!("{dropped} frames dropped, the encoder can't keep up")).small());
+        }
+    } else if ui.button("Start Recording").clicked() {
+        recording.start();
+    }
+
+    status_label(ui, &recording.status);
+}
+
+pub(super) fn status_label(ui: &mut egui::Ui, status: &Option<Result<String, String>>) {
+    match status {
+        Some(Ok(message)) => {
+            ui.label(egui::RichText::new(message).small());
+        }
+        Some(Err(message)) => {
+            ui.label(egui::RichText::new(message).small().color(egui::Color32::LIGHT_RED));
+        }
+        None => {}
+    }
+}
@@ -1,12 +1,21 @@
 pub mod ui;
+pub mod files;
+pub mod world;
 
 use bevy::prelude::*;
-use ui::ui_system;
+use ui::{dirty_regions_overlay, shape_preview_system, ui_system};
+use files::{ExportFile, ImportFile, SaveFile};
+use world::WorldSize;
 
 pub struct UIPlugin;
 
 impl Plugin for UIPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, ui_system);
+        app
+            .init_resource::<SaveFile>()
+            .init_resource::<ImportFile>()
+            .init_resource::<ExportFile>()
+            .init_resource::<WorldSize>()
+            .add_systems(Update, (ui_system, shape_preview_system, dirty_regions_overlay));
     }
 }
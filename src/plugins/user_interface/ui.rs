@@ -1,16 +1,40 @@
 use bevy::prelude::*;
 use bevy_egui::{egui, EguiContexts};
-use crate::config::UI_PANEL_DEFAULT_WIDTH;
-use crate::plugins::input::resources::{BrushSize, SelectedMaterial};
-use crate::registry::MATERIAL_NAMES;
+use crate::config::{MAX_TICKS_PER_SECOND, MIN_TICKS_PER_SECOND, UI_PANEL_DEFAULT_WIDTH};
+use crate::grid::Grid;
+use crate::plugins::input::input::ShapeDrag;
+use crate::image_io::load_stamp;
+use crate::plugins::input::resources::{Brush, BrushMode, BrushShape, BrushSize, FillShapes, Tool};
+use crate::materials::Material;
+use crate::materials::properties::MAX_BLAST_RADIUS;
+use crate::registry::MaterialRegistry;
+use crate::settings::Settings;
+use crate::systems::{DebugView, ParallelUpdate, RenderMode, SidebarWidth, SimulationControl, TextureUpdate, TickTime};
+use crate::utils::rng::SimulationRng;
 use bevy::window::PrimaryWindow;
-use crate::utils::grid_utils::get_grid_pos;
+use crate::utils::grid_utils::{get_cell_rect, get_grid_pos};
+use super::files::{export_controls, file_controls, import_controls, recording_controls, status_label, Files};
+use super::world::{world_size_controls, WorldSize};
+
+const PREVIEW_COLOR: egui::Color32 = egui::Color32::from_rgba_premultiplied(255, 255, 255, 96);
+const DIRTY_REGION_COLOR: egui::Color32 = egui::Color32::from_rgb(255, 0, 255);
 
 /// UI system that displays a sidebar with material selection buttons and a brush preview.
+#[allow(clippy::too_many_arguments)]
 pub fn ui_system(
     mut egui_context: EguiContexts,
-    mut selected_material: ResMut<SelectedMaterial>,
-    mut brush_size: ResMut<BrushSize>,
+    mut brush: Brush,
+    registry: Res<MaterialRegistry>,
+    mut parallel: ResMut<ParallelUpdate>,
+    mut debug_view: ResMut<DebugView>,
+    mut control: ResMut<SimulationControl>,
+    tick_time: Res<TickTime>,
+    rng: Res<SimulationRng>,
+    mut grid: ResMut<Grid>,
+    mut files: Files,
+    mut world_size: ResMut<WorldSize>,
+    settings: Res<Settings>,
+    mut sidebar_width: ResMut<SidebarWidth>,
     window_query: Query<&Window, With<PrimaryWindow>>,
     camera_q: Query<(&Camera, &GlobalTransform)>,
 ) {
@@ -19,39 +43,265 @@ pub fn ui_system(
 
     // Draw the brush preview circle only when mouse is over the grid
     if let Some(cursor_pos) = window.cursor_position() {
-        if get_grid_pos(window, camera, camera_transform).is_some() {
-            let radius = brush_size.0 as f32 * 2.0; // Multiply by 2 for better visibility
+        let hovered_cell = get_grid_pos(window, camera, camera_transform, &grid, settings.cell_size)
+            .and_then(|(x, y)| get_cell_rect(camera, camera_transform, &grid, settings.cell_size, x, y));
+        if let Some(cell) = hovered_cell {
+            let radius = brush.size.0 as f32 * cell.width() / 2.0;
             let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("brush_preview")));
-            painter.circle_stroke(
-                egui::pos2(cursor_pos.x, cursor_pos.y),
-                radius,
-                egui::Stroke::new(1.0, egui::Color32::WHITE),
-            );
+            let center = egui::pos2(cursor_pos.x, cursor_pos.y);
+            let stroke = egui::Stroke::new(1.0, egui::Color32::WHITE);
+            match *brush.shape {
+                BrushShape::Square => {
+                    let rect = egui::Rect::from_center_size(center, egui::Vec2::splat(radius * 2.0));
+                    painter.rect_stroke(rect, 0.0, stroke, egui::StrokeKind::Middle);
+                }
+                BrushShape::Diamond => {
+                    let corners = [(0.0, -radius), (radius, 0.0), (0.0, radius), (-radius, 0.0)];
+                    painter.add(egui::Shape::closed_line(corners.map(|(x, y)| center + egui::vec2(x, y)).to_vec(), stroke));
+                }
+                BrushShape::Circle | BrushShape::Stamp => {
+                    painter.circle_stroke(center, radius, stroke);
+                }
+            }
         }
     }
 
     // Original sidebar UI
-    egui::SidePanel::right("right_panel")
+    let panel = egui::SidePanel::right("right_panel")
         .resizable(false)
         .default_width(UI_PANEL_DEFAULT_WIDTH)
         .show(egui_context.ctx_mut(), |ui| {
-            ui.heading("Select Material");
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.heading("Select Material");
 
-            for (id, name) in MATERIAL_NAMES.iter().enumerate() {
-                if ui.selectable_label(selected_material.0 == id as u8, *name).clicked() {
-                    selected_material.0 = id as u8;
+                for category in registry.categories() {
+                    ui.label(egui::RichText::new(category).small().weak());
+                    for (material, definition) in registry.iter().filter(|(_, d)| d.category == category) {
+                        if ui.selectable_label(brush.material.0 == material.id(), &definition.name).clicked() {
+                            brush.material.0 = material.id();
+                        }
+                    }
                 }
-            }
 
-            ui.separator();
-            ui.label("Current Material:");
-            let current_name = if (selected_material.0 as usize) < MATERIAL_NAMES.len() {
-                MATERIAL_NAMES[selected_material.0 as usize]
-            } else {
-                "Unknown"
-            };
-            ui.label(current_name);
-            ui.separator();
-            ui.add(egui::Slider::new(&mut brush_size.0, 1..=30).text("Brush Size"));
+                ui.separator();
+                ui.label("Current Material:");
+                ui.label(registry.name(Material::from_id(brush.material.0)));
+                ui.separator();
+                ui.add(egui::Slider::new(&mut brush.size.0, 1..=30).text("Brush Size"));
+                brush_shape_controls(ui, &mut brush);
+                ui.separator();
+                tool_controls(ui, &mut brush);
+                ui.separator();
+                brush_mode_controls(ui, &mut brush, &registry);
+                ui.separator();
+                simulation_controls(ui, &mut control);
+                ui.checkbox(&mut parallel.0, "Parallel update");
+                ui.label(format!("Tick: {:.2} ms", tick_time.0.as_secs_f64() * 1000.0));
+                ui.label(format!("Seed: {}", rng.seed()));
+                ui.separator();
+                debug_view_controls(ui, &mut debug_view);
+                ui.separator();
+                world_size_controls(ui, &mut grid, &mut world_size);
+                ui.separator();
+                file_controls(ui, &mut grid, &registry, &mut files.save);
+                ui.separator();
+                import_controls(ui, &mut grid, &registry, &mut files.import);
+                ui.separator();
+                export_controls(ui, &grid, settings.cell_size, &mut files.export);
+                ui.separator();
+                recording_controls(ui, settings.cell_size, &mut files.recording);
+            });
+        });
+    // The camera draws the grid in the rest of the window
+    let width = panel.response.rect.width();
+    if sidebar_width.0 != width {
+        sidebar_width.0 = width;
+    }
+}
+
+/// Sidebar section for pausing, stepping and speeding up the simulation
+fn simulation_controls(ui: &mut egui::Ui, control: &mut SimulationControl) {
+    ui.horizontal(|ui| {
+        let label = if control.paused { "Resume (Space)" } else { "Pause (Space)" };
+        if ui.button(label).clicked() {
+            control.paused = !control.paused;
+        }
+        if ui.button("Step (.)").clicked() {
+            control.step();
+        }
+    });
+    ui.add(
+        egui::Slider::new(&mut control.speed, SimulationControl::MIN_SPEED..=SimulationControl::MAX_SPEED)
+            .logarithmic(true)
+            .text("Speed"),
+    );
+    ui.add(
+        egui::Slider::new(&mut control.ticks_per_second, MIN_TICKS_PER_SECOND..=MAX_TICKS_PER_SECOND)
+            .logarithmic(true)
+            .integer()
+            .text("Ticks/s"),
+    );
+}
+
+/// Sidebar section for picking what the grid shows, to look into per-cell state
+fn debug_view_controls(ui: &mut egui::Ui, debug_view: &mut DebugView) {
+    egui::ComboBox::from_label("View")
+        .selected_text(debug_view.mode.name())
+        .show_ui(ui, |ui| {
+            for mode in RenderMode::ALL {
+                ui.selectable_value(&mut debug_view.mode, mode, mode.name());
+            }
         });
+    ui.checkbox(&mut debug_view.show_dirty_regions, "Show redrawn regions");
+}
+
+/// Sidebar section for the brush footprint, spraying and the velocity given to painted particles
+fn brush_shape_controls(ui: &mut egui::Ui, brush: &mut Brush) {
+    ui.label("Brush Shape:");
+    ui.horizontal_wrapped(|ui| {
+        for shape in BrushShape::ALL {
+            ui.radio_value(&mut *brush.shape, shape, shape.name());
+        }
+    });
+
+    if *brush.shape == BrushShape::Stamp {
+        let stamp = &mut *brush.stamp;
+        ui.text_edit_singleline(&mut stamp.path);
+        // Try the default stamp once, so the shape works without a manual load
+        let first_use = stamp.stamp.is_none() && stamp.status.is_none();
+        if ui.button("Load Stamp").clicked() || first_use {
+            stamp.status = Some(match load_stamp(&stamp.path) {
+                Ok(loaded) => {
+                    stamp.stamp = Some(loaded);
+                    Ok(format!("Loaded {}", stamp.path))
+                }
+                Err(err) => Err(format!("Stamp failed, painting circles: {err}")),
+            });
+        }
+        status_label(ui, &stamp.status);
+    }
+
+    ui.checkbox(&mut brush.spray.enabled, "Spray");
+    if brush.spray.enabled {
+        ui.add(egui::Slider::new(&mut brush.spray.density, 1..=100).text("Density %"));
+    }
+
+    ui.checkbox(&mut brush.pour.enabled, "Initial velocity");
+    if brush.pour.enabled {
+        ui.add(egui::Slider::new(&mut brush.pour.velocity.x, -8.0..=8.0).text("Right"));
+        ui.add(egui::Slider::new(&mut brush.pour.velocity.y, -8.0..=8.0).text("Down"));
+    }
+}
+
+/// Sidebar section for picking the drawing tool
+fn tool_controls(ui: &mut egui::Ui, brush: &mut Brush) {
+    ui.label("Tool:");
+    for tool in Tool::ALL {
+        ui.radio_value(&mut *brush.tool, tool, tool.name());
+    }
+    if matches!(*brush.tool, Tool::Rectangle | Tool::Ellipse) {
+        ui.checkbox(&mut brush.fill_shapes.0, "Filled");
+    }
+    if *brush.tool == Tool::Fill {
+        status_label(ui, &brush.fill_status.0);
+    }
+    if *brush.tool == Tool::Explosion {
+        ui.add(egui::Slider::new(&mut brush.explosion_radius.0, 1..=MAX_BLAST_RADIUS).text("Radius"));
+    }
+}
+
+/// Sidebar section for picking how the brush treats the cells it covers
+fn brush_mode_controls(ui: &mut egui::Ui, brush: &mut Brush, registry: &MaterialRegistry) {
+    ui.label("Brush Mode (B/O/E/R):");
+    for mode in BrushMode::ALL {
+        ui.radio_value(&mut *brush.mode, mode, mode.name());
+    }
+
+    if *brush.mode == BrushMode::Replace {
+        let source = &mut brush.replace_source.0;
+        egui::ComboBox::from_label("Replaces")
+            .selected_text(registry.name(Material::from_id(*source)))
+            .show_ui(ui, |ui| {
+                for (material, definition) in registry.iter() {
+                    ui.selectable_value(source, material.id(), &definition.name);
+                }
+            });
+    }
+}
+
+/// Previews the shape being dragged out, until it is drawn on release
+#[allow(clippy::too_many_arguments)]
+pub fn shape_preview_system(
+    mut egui_context: EguiContexts,
+    shape_drag: Res<ShapeDrag>,
+    tool: Res<Tool>,
+    fill_shapes: Res<FillShapes>,
+    brush_size: Res<BrushSize>,
+    grid: Res<Grid>,
+    settings: Res<Settings>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    let Some((start, end)) = shape_drag.0 else { return };
+    let (camera, camera_transform) = camera_q.single();
+    let (Some(start), Some(end)) = (
+        get_cell_rect(camera, camera_transform, &grid, settings.cell_size, start.0, start.1),
+        get_cell_rect(camera, camera_transform, &grid, settings.cell_size, end.0, end.1),
+    ) else {
+        return;
+    };
+
+    let to_pos = |point: Vec2| egui::pos2(point.x, point.y);
+    let bounds = egui::Rect::from_min_max(to_pos(start.min.min(end.min)), to_pos(start.max.max(end.max)));
+    // Lines and outlines are traced with the brush, so they are as thick as it is
+    let brush_stroke = egui::Stroke::new(brush_size.0 as f32 * start.width(), PREVIEW_COLOR);
+    let fill = PREVIEW_COLOR.gamma_multiply(0.5);
+
+    let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(egui::Order::Foreground, egui::Id::new("shape_preview")));
+    match (*tool, fill_shapes.0) {
+        (Tool::Line, _) => {
+            painter.line_segment([to_pos(start.center()), to_pos(end.center())], brush_stroke);
+        }
+        (Tool::Rectangle, true) => {
+            painter.rect_filled(bounds, 0.0, fill);
+        }
+        (Tool::Rectangle, false) => {
+            painter.rect_stroke(bounds.shrink(start.width() / 2.0), 0.0, brush_stroke, egui::StrokeKind::Middle);
+        }
+        (Tool::Ellipse, true) => {
+            painter.add(egui::Shape::ellipse_filled(bounds.center(), bounds.size() / 2.0, fill));
+        }
+        (Tool::Ellipse, false) => {
+            let radius = (bounds.size() - egui::vec2(start.width(), start.height())) / 2.0;
+            painter.add(egui::Shape::ellipse_stroke(bounds.center(), radius, brush_stroke));
+        }
+        (Tool::Brush | Tool::Fill | Tool::Explosion, _) => {}
+    }
+}
+
+/// Outlines the regions of the grid texture redrawn this frame
+pub fn dirty_regions_overlay(
+    mut egui_context: EguiContexts,
+    debug_view: Res<DebugView>,
+    texture_update: Res<TextureUpdate>,
+    grid: Res<Grid>,
+    settings: Res<Settings>,
+    camera_q: Query<(&Camera, &GlobalTransform)>,
+) {
+    if !debug_view.show_dirty_regions {
+        return;
+    }
+    let (camera, camera_transform) = camera_q.single();
+    let painter = egui_context.ctx_mut().layer_painter(egui::LayerId::new(egui::Order::Background, egui::Id::new("dirty_regions")));
+    let stroke = egui::Stroke::new(1.0, DIRTY_REGION_COLOR);
+    for region in &texture_update.regions {
+        let rect = region.rect;
+        let (Some(first), Some(last)) = (
+            get_cell_rect(camera, camera_transform, &grid, settings.cell_size, rect.min.x as usize, rect.min.y as usize),
+            get_cell_rect(camera, camera_transform, &grid, settings.cell_size, rect.max.x as usize - 1, rect.max.y as usize - 1),
+        ) else {
+            continue;
+        };
+        let bounds = egui::Rect::from_min_max(egui::pos2(first.min.x, first.min.y), egui::pos2(last.max.x, last.max.y));
+        painter.rect_stroke(bounds, 0.0, stroke, egui::StrokeKind::Inside);
+    }
 }
@@ -0,0 +1,33 @@
+use bevy::prelude::*;
+use bevy_egui::egui;
+use crate::config::{MAX_GRID_SIZE, MIN_GRID_SIZE};
+use crate::grid::Grid;
+
+/// The grid size picked in the sidebar, applied with the resize button
+#[derive(Resource, Default)]
+pub struct WorldSize {
+    pub width: usize,
+    pub height: usize,
+    grid_size: (usize, usize), // Size of the grid when last shown, to notice it being replaced
+}
+
+/// Sidebar section for resizing the world, keeping its contents anchored to the bottom
+pub fn world_size_controls(ui: &mut egui::Ui, grid: &mut Grid, world_size: &mut WorldSize) {
+    // Loading or importing may have resized the grid, so start over from its new size
+    let grid_size = (grid.width(), grid.height());
+    if world_size.grid_size != grid_size {
+        (world_size.width, world_size.height) = grid_size;
+        world_size.grid_size = grid_size;
+    }
+
+    ui.label(format!("World Size: {}x{}", grid.width(), grid.height()));
+    ui.horizontal(|ui| {
+        ui.add(egui::DragValue::new(&mut world_size.width).range(MIN_GRID_SIZE..=MAX_GRID_SIZE));
+        ui.label("x");
+        ui.add(egui::DragValue::new(&mut world_size.height).range(MIN_GRID_SIZE..=MAX_GRID_SIZE));
+        let unchanged = (world_size.width, world_size.height) == (grid.width(), grid.height());
+        if ui.add_enabled(!unchanged, egui::Button::new("Resize")).clicked() {
+            *grid = grid.resized(world_size.width, world_size.height);
+        }
+    });
+}
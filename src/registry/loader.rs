@@ -0,0 +1,355 @@
+use std::fmt;
+use std::path::PathBuf;
+use ron::extensions::Extensions;
+use serde::Deserialize;
+use crate::materials::{Material, MaterialProperties, PhaseTransition, Reaction, TransitionCondition};
+use crate::materials::properties::{MAX_BLAST_RADIUS, MAX_COLOR_VARIATION, MAX_DENSITY, MAX_VISCOSITY};
+use crate::materials::thermal::AMBIENT_TEMPERATURE;
+use crate::materials::types::ParticleFlags;
+
+use super::{BuiltinMaterials, MaterialDefinition};
+
+/// A material entry exactly as written in the definitions file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MaterialEntry {
+    name: String,
+    category: String,
+    color: (u8, u8, u8),
+    #[serde(default)]
+    color_variation: u8,
+    density: u8,
+    viscosity: u8,
+    #[serde(default)]
+    flags: Vec<String>,
+    conductivity: f32,
+    heat_capacity: f32,
+    #[serde(default = "ambient_temperature")]
+    temperature: f32,
+    #[serde(default)]
+    flammability: f32,
+    #[serde(default)]
+    fuel: u8,
+    #[serde(default)]
+    burns_into: Option<String>,
+    #[serde(default)]
+    blast_radius: u8,
+    #[serde(default)]
+    transitions: Vec<TransitionEntry>,
+    #[serde(default)]
+    reactions: Vec<ReactionEntry>,
+}
+
+/// A phase transition as written in the definitions file.
+/// Without `above` or `below` the transition is rolled every tick.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TransitionEntry {
+    into: String,
+    #[serde(default)]
+    above: Option<f32>,
+    #[serde(default)]
+    below: Option<f32>,
+    chance: f32,
+}
+
+fn ambient_temperature() -> f32 {
+    AMBIENT_TEMPERATURE
+}
+
+/// A reaction as written in the definitions file.
+/// Omitting `into` or `other_into` leaves that cell's material unchanged.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ReactionEntry {
+    with: String,
+    #[serde(default)]
+    into: Option<String>,
+    #[serde(default)]
+    other_into: Option<String>,
+    #[serde(default)]
+    emit: Option<String>,
+    #[serde(default)]
+    heat: f32,
+    chance: f32,
+}
+
+/// Errors produced while loading the material definitions
+#[derive(Debug)]
+pub enum RegistryError {
+    /// The definitions file couldn't be read
+    Io { path: PathBuf, source: std::io::Error },
+    /// The definitions file isn't valid RON or doesn't match the expected layout
+    Parse(ron::error::SpannedError),
+    /// An entry parsed but holds an invalid value
+    Invalid { index: usize, name: String, field: &'static str, reason: String },
+    /// A material the simulation refers to by name isn't defined
+    MissingBuiltin(&'static str),
+}
+
+impl fmt::Display for RegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RegistryError::Io { path, source } => write!(f, "could not read {}: {source}", path.display()),
+            RegistryError::Parse(err) => write!(f, "invalid material definitions: {err}"),
+            RegistryError::Invalid { index, name, field, reason } => {
+                write!(f, "material #{index} ({name:?}), field `{field}`: {reason}")
+            }
+            RegistryError::MissingBuiltin(name) => write!(f, "the built-in material {name:?} is missing"),
+        }
+    }
+}
+
+impl std::error::Error for RegistryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RegistryError::Io { source, .. } => Some(source),
+            RegistryError::Parse(err) => Some(err),
+            RegistryError::Invalid { .. } | RegistryError::MissingBuiltin(_) => None,
+        }
+    }
+}
+
+/// Parses the definitions file and validates every entry.
+/// Also returns the ids of the built-in materials, wherever the file lists them.
+pub(super) fn parse_definitions(source: &str) -> Result<(Vec<MaterialDefinition>, BuiltinMaterials), RegistryError> {
+    let entries: Vec<MaterialEntry> = ron::Options::default()
+        .with_default_extension(Extensions::IMPLICIT_SOME)
+        .from_str(source)
+        .map_err(RegistryError::Parse)?;
+
+    if entries.len() > u8::MAX as usize + 1 {
+        return Err(RegistryError::Invalid {
+            index: entries.len() - 1,
+            name: entries[entries.len() - 1].name.clone(),
+            field: "name",
+            reason: format!("at most {} materials are supported", u8::MAX as usize + 1),
+        });
+    }
+
+    // Transitions may refer to materials defined further down the file
+    let names: Vec<&str> = entries.iter().map(|entry| entry.name.as_str()).collect();
+    let builtin = |name: &'static str| {
+        names
+            .iter()
+            .position(|known| *known == name)
+            .map(|id| Material::from_id(id as u8))
+            .ok_or(RegistryError::MissingBuiltin(name))
+    };
+    let builtin = BuiltinMaterials {
+        empty: builtin("Empty")?,
+        sand: builtin("Sand")?,
+        smoke: builtin("Smoke")?,
+        fire: builtin("Fire")?,
+    };
+
+    let mut definitions = Vec::with_capacity(entries.len());
+    for (index, entry) in entries.iter().enumerate() {
+        let invalid = |field: &'static str, reason: String| RegistryError::Invalid {
+            index,
+            name: entry.name.clone(),
+            field,
+            reason,
+        };
+
+        if entry.name.trim().is_empty() {
+            return Err(invalid("name", "must not be empty".into()));
+        }
+        if names[..index].contains(&entry.name.as_str()) {
+            return Err(invalid("name", "is defined more than once".into()));
+        }
+        if entry.category.trim().is_empty() {
+            return Err(invalid("category", "must not be empty".into()));
+        }
+        if entry.density > MAX_DENSITY {
+            return Err(invalid("density", format!("{} exceeds the maximum of {MAX_DENSITY}", entry.density)));
+        }
+        if entry.viscosity > MAX_VISCOSITY {
+            return Err(invalid("viscosity", format!("{} exceeds the maximum of {MAX_VISCOSITY}", entry.viscosity)));
+        }
+        if !(0.0..=1.0).contains(&entry.conductivity) {
+            return Err(invalid("conductivity", format!("{} is outside the range 0.0-1.0", entry.conductivity)));
+        }
+        // Below 1.0 a cell could overshoot its neighbours' temperature in a single tick
+        if entry.heat_capacity.is_nan() || entry.heat_capacity < 1.0 {
+            return Err(invalid("heat_capacity", format!("{} is below the minimum of 1.0", entry.heat_capacity)));
+        }
+        if !entry.temperature.is_finite() {
+            return Err(invalid("temperature", "must be a finite number".into()));
+        }
+
+        let mut flags = ParticleFlags::empty();
+        for flag in &entry.flags {
+            match ParticleFlags::from_name(flag) {
+                Some(ParticleFlags::BURNING) => {
+                    return Err(invalid("flags", "BURNING is set by the simulation and can't be declared".into()));
+                }
+                Some(parsed) => flags |= parsed,
+                None => return Err(invalid("flags", format!("unknown flag {flag:?}"))),
+            }
+        }
+
+        let resolve = |field: &'static str, name: &str| {
+            names
+                .iter()
+                .position(|known| *known == name)
+                .map(|id| Material::from_id(id as u8))
+                .ok_or_else(|| invalid(field, format!("unknown material {name:?}")))
+        };
+
+        if !(0.0..=1.0).contains(&entry.flammability) {
+            return Err(invalid("flammability", format!("{} is outside the range 0.0-1.0", entry.flammability)));
+        }
+        if entry.flammability > 0.0 && entry.fuel == 0 {
+            return Err(invalid("fuel", "flammable materials need at least 1 tick of fuel".into()));
+        }
+        if entry.color_variation > MAX_COLOR_VARIATION {
+            return Err(invalid("color_variation", format!("{} exceeds the maximum of {MAX_COLOR_VARIATION}", entry.color_variation)));
+        }
+        if entry.blast_radius > MAX_BLAST_RADIUS {
+            return Err(invalid("blast_radius", format!("{} exceeds the maximum of {MAX_BLAST_RADIUS}", entry.blast_radius)));
+        }
+        if entry.blast_radius > 0 && entry.flammability <= 0.0 {
+            return Err(invalid("blast_radius", "only flammable materials can explode".into()));
+        }
+        let burns_into = match &entry.burns_into {
+            Some(name) => resolve("burns_into", name)?,
+            None => builtin.empty,
+        };
+
+        let mut transitions = Vec::with_capacity(entry.transitions.len());
+        for transition in &entry.transitions {
+            let into = resolve("transitions", &transition.into)?;
+            if transition.above.into_iter().chain(transition.below).any(|threshold| !threshold.is_finite()) {
+                return Err(invalid("transitions", format!("threshold of transition into {:?} must be a finite number", transition.into)));
+            }
+            let condition = match (transition.above, transition.below) {
+                (Some(threshold), None) => TransitionCondition::Above(threshold),
+                (None, Some(threshold)) => TransitionCondition::Below(threshold),
+                (None, None) => TransitionCondition::Always,
+                (Some(_), Some(_)) => {
+                    return Err(invalid("transitions", format!("transition into {:?} sets both `above` and `below`", transition.into)));
+                }
+            };
+            if !(0.0..=1.0).contains(&transition.chance) {
+                return Err(invalid("transitions", format!("chance {} of transition into {:?} is outside the range 0.0-1.0", transition.chance, transition.into)));
+            }
+            transitions.push(PhaseTransition {
+                into,
+                condition,
+                chance: transition.chance,
+            });
+        }
+
+        let mut reactions = Vec::with_capacity(entry.reactions.len());
+        for reaction in &entry.reactions {
+            let with = resolve("reactions", &reaction.with)?;
+            let into = reaction.into.as_deref().map(|name| resolve("reactions", name)).transpose()?;
+            let other_into = reaction.other_into.as_deref().map(|name| resolve("reactions", name)).transpose()?;
+            let emit = reaction.emit.as_deref().map(|name| resolve("reactions", name)).transpose()?;
+
+            if !(0.0..=1.0).contains(&reaction.chance) {
+                return Err(invalid("reactions", format!("chance {} of reaction with {:?} is outside the range 0.0-1.0", reaction.chance, reaction.with)));
+            }
+            if !reaction.heat.is_finite() {
+                return Err(invalid("reactions", format!("heat of reaction with {:?} must be a finite number", reaction.with)));
+            }
+            if into.is_none() && other_into.is_none() && emit.is_none() && reaction.heat == 0.0 {
+                return Err(invalid("reactions", format!("reaction with {:?} has no effect", reaction.with)));
+            }
+            reactions.push(Reaction {
+                with,
+                into,
+                other_into,
+                emit,
+                heat: reaction.heat,
+                chance: reaction.chance,
+            });
+        }
+
+        definitions.push(MaterialDefinition {
+            name: entry.name.clone(),
+            category: entry.category.clone(),
+            properties: MaterialProperties {
+                color: entry.color,
+                color_variation: entry.color_variation,
+                density: entry.density,
+                viscosity: entry.viscosity,
+                flags,
+                conductivity: entry.conductivity,
+                heat_capacity: entry.heat_capacity,
+                temperature: entry.temperature,
+                flammability: entry.flammability,
+                fuel: entry.fuel,
+                burns_into,
+                blast_radius: entry.blast_radius,
+            },
+            transitions,
+            reactions,
+        });
+    }
+
+    Ok((definitions, builtin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The materials every definitions file needs, followed by `entry` as material #4
+    fn parse_with(entry: &str) -> Result<(Vec<MaterialDefinition>, BuiltinMaterials), RegistryError> {
+        let builtin = ["Empty", "Sand", "Smoke", "Fire"]
+            .map(|name| format!("(name: {name:?}, category: \"Test\", color: (0, 0, 0), density: 0, viscosity: 0, conductivity: 0.5, heat_capacity: 1.0),"))
+            .concat();
+        parse_definitions(&format!("[{builtin} {entry}]"))
+    }
+
+    fn assert_invalid(entry: &str, expected_field: &str) {
+        match parse_with(entry) {
+            Err(RegistryError::Invalid { index, name, field, .. }) => {
+                assert_eq!((index, name.as_str(), field), (4, "Stone", expected_field));
+            }
+            Err(err) => panic!("expected an invalid {expected_field}, got {err}"),
+            Ok(_) => panic!("expected an invalid {expected_field}, but the definitions loaded"),
+        }
+    }
+
+    fn stone(fields: &str) -> String {
+        format!("(name: \"Stone\", category: \"Solids\", color: (90, 90, 90), density: 20, viscosity: 0, conductivity: 0.5, {fields})")
+    }
+
+    #[test]
+    fn valid_definitions_load() {
+        let (definitions, builtin) = parse_with(&stone("heat_capacity: 2.0, transitions: [(into: \"Fire\", above: 900.0, chance: 0.5)]")).unwrap();
+        assert_eq!(definitions.len(), 5);
+        assert_eq!(definitions[4].transitions[0].into, builtin.fire);
+    }
+
+    #[test]
+    fn unknown_transition_targets_are_rejected() {
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Lava\", above: 900.0, chance: 0.5)]"), "transitions");
+    }
+
+    #[test]
+    fn transitions_with_both_thresholds_are_rejected() {
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Sand\", above: 900.0, below: 0.0, chance: 0.5)]"), "transitions");
+    }
+
+    #[test]
+    fn transition_thresholds_must_be_finite() {
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Sand\", above: inf, chance: 0.5)]"), "transitions");
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Sand\", below: NaN, chance: 0.5)]"), "transitions");
+    }
+
+    #[test]
+    fn transition_chances_out_of_range_are_rejected() {
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Sand\", chance: 1.5)]"), "transitions");
+        assert_invalid(&stone("heat_capacity: 2.0, transitions: [(into: \"Sand\", chance: -0.1)]"), "transitions");
+    }
+
+    #[test]
+    fn heat_capacities_below_one_are_rejected() {
+        assert_invalid(&stone("heat_capacity: 0.5"), "heat_capacity");
+        assert_invalid(&stone("heat_capacity: NaN"), "heat_capacity");
+    }
+}
@@ -1,11 +1,144 @@
-use crate::materials::Material;
+mod loader;
 
-pub static MATERIAL_NAMES: [&str; 6] = [
-    "Empty",
-    "Sand",
-    "Water",
-    "Concrete",
-    "Smoke",
-    "Fire",
-];
+use std::path::Path;
+use std::sync::Arc;
+use bevy::prelude::*;
+use crate::materials::{Material, MaterialProperties, PhaseTransition, Reaction};
+use crate::materials::types::Particle;
 
+pub use loader::RegistryError;
+
+/// Definitions shipped with the binary, used when the definitions file can't be loaded
+const BUILTIN_DEFINITIONS: &str = include_str!("../../assets/materials.ron");
+
+/// A single material as described by the definitions file
+#[derive(Debug, Clone)]
+pub struct MaterialDefinition {
+    pub name: String,
+    pub category: String,
+    pub properties: MaterialProperties,
+    pub transitions: Vec<PhaseTransition>,
+    pub reactions: Vec<Reaction>,
+}
+
+/// The materials the simulation itself refers to.
+/// They are looked up by name, so the definitions file may list them in any order.
+#[derive(Debug, Clone, Copy)]
+struct BuiltinMaterials {
+    empty: Material,
+    sand: Material,
+    smoke: Material,
+    fire: Material,
+}
+
+/// All known materials, indexed by material id.
+/// Cloning is cheap, so the grid keeps its own handle for creating particles.
+#[derive(Resource, Clone)]
+pub struct MaterialRegistry {
+    definitions: Arc<[MaterialDefinition]>,
+    builtin: BuiltinMaterials,
+}
+
+impl MaterialRegistry {
+    /// Parses and validates a RON definitions string
+    pub fn from_ron(source: &str) -> Result<Self, RegistryError> {
+        let (definitions, builtin) = loader::parse_definitions(source)?;
+        Ok(Self { definitions: definitions.into(), builtin })
+    }
+
+    /// Loads the registry from a definitions file on disk
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, RegistryError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path).map_err(|source| RegistryError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::from_ron(&source)
+    }
+
+    /// Loads the registry from disk, falling back to the built-in definitions on error
+    pub fn load_or_builtin(path: impl AsRef<Path>) -> Self {
+        Self::load(&path).unwrap_or_else(|err| {
+            error!("Failed to load materials from {}: {err}", path.as_ref().display());
+            warn!("Falling back to built-in material definitions");
+            Self::builtin()
+        })
+    }
+
+    /// The material definitions compiled into the binary
+    pub fn builtin() -> Self {
+        Self::from_ron(BUILTIN_DEFINITIONS).expect("built-in material definitions are valid")
+    }
+
+    /// Gets the definition of a material, falling back to Empty for unknown ids
+    pub fn get(&self, material: Material) -> &MaterialDefinition {
+        self.definitions
+            .get(material.id() as usize)
+            .unwrap_or(&self.definitions[self.builtin.empty.id() as usize])
+    }
+
+    /// The material of cells holding nothing
+    pub fn empty(&self) -> Material {
+        self.builtin.empty
+    }
+
+    pub fn sand(&self) -> Material {
+        self.builtin.sand
+    }
+
+    pub fn smoke(&self) -> Material {
+        self.builtin.smoke
+    }
+
+    pub fn fire(&self) -> Material {
+        self.builtin.fire
+    }
+
+    /// Looks up a material by its name
+    pub fn find(&self, name: &str) -> Option<Material> {
+        self.definitions
+            .iter()
+            .position(|definition| definition.name == name)
+            .map(|id| Material::from_id(id as u8))
+    }
+
+    pub fn properties(&self, material: Material) -> &MaterialProperties {
+        &self.get(material).properties
+    }
+
+    pub fn transitions(&self, material: Material) -> &[PhaseTransition] {
+        &self.get(material).transitions
+    }
+
+    pub fn reactions(&self, material: Material) -> &[Reaction] {
+        &self.get(material).reactions
+    }
+
+    pub fn name(&self, material: Material) -> &str {
+        &self.get(material).name
+    }
+
+    /// Creates a fresh particle of the given material
+    pub fn particle(&self, material: Material) -> Particle {
+        Particle::new(material, self.properties(material))
+    }
+
+    /// Iterates over all materials in id order
+    pub fn iter(&self) -> impl Iterator<Item = (Material, &MaterialDefinition)> {
+        self.definitions
+            .iter()
+            .enumerate()
+            .map(|(id, definition)| (Material::from_id(id as u8), definition))
+    }
+
+    /// Lists UI categories in the order they first appear in the definitions
+    pub fn categories(&self) -> Vec<&str> {
+        let mut categories: Vec<&str> = Vec::new();
+        for definition in self.definitions.iter() {
+            if !categories.contains(&definition.category.as_str()) {
+                categories.push(&definition.category);
+            }
+        }
+        categories
+    }
+}
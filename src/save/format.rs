@@ -0,0 +1,355 @@
+use std::fmt;
+use std::path::PathBuf;
+use bevy::math::Vec2;
+use crate::config::MAX_GRID_SIZE;
+use crate::grid::Grid;
+use crate::materials::types::ParticleFlags;
+use crate::registry::MaterialRegistry;
+
+// File layout, all numbers little-endian:
+//
+//   magic        8 bytes, "SANDGRID"
+//   version      u16
+//   width        u32
+//   height       u32
+//   materials    u16 count, then per material a u16 name length and the UTF-8 name.
+//                Cells refer to materials by their index in this table.
+//   channels     particles, velocities, temperatures and fuel, one after the other.
+//                Each is a list of runs covering every cell in row order: a LEB128
+//                run length followed by the record repeated that many times.
+//   checksum     u32, FNV-1a of everything before it
+//
+// Particle records are the material index, the runtime flags and the RGB colour
+// (version 1 stored colours quantized, so they are replaced by the material's);
+// velocities are two f32, temperatures one f32 and fuel a single byte.
+
+const MAGIC: &[u8; 8] = b"SANDGRID";
+pub const FORMAT_VERSION: u16 = 2;
+
+/// Flags set by the simulation rather than the material definition, kept across saves
+const RUNTIME_FLAGS: ParticleFlags = ParticleFlags::BURNING;
+
+/// Errors produced while saving or loading a grid
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum SaveError {
+    /// The file couldn't be read or written
+    Io { path: PathBuf, source: std::io::Error },
+    /// The file doesn't start with the save file signature
+    NotASaveFile,
+    /// The file was written by a newer version of the format
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The file ends before all of its data was read
+    Truncated,
+    /// The file is damaged or inconsistent
+    Corrupt(String),
+    /// The file uses a material the current definitions don't have
+    UnknownMaterial(String),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io { path, source } => write!(f, "could not access {}: {source}", path.display()),
+            SaveError::NotASaveFile => write!(f, "not a grid save file"),
+            SaveError::UnsupportedVersion { found, supported } => {
+                write!(f, "save file version {found} is newer than the supported version {supported}")
+            }
+            SaveError::Truncated => write!(f, "save file is truncated"),
+            SaveError::Corrupt(reason) => write!(f, "save file is corrupt: {reason}"),
+            SaveError::UnknownMaterial(name) => write!(f, "save file uses unknown material {name:?}"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SaveError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Serializes the grid into the save file format
+pub fn encode(grid: &Grid) -> Vec<u8> {
+    let registry = grid.registry();
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+    out.extend_from_slice(&(grid.width() as u32).to_le_bytes());
+    out.extend_from_slice(&(grid.height() as u32).to_le_bytes());
+
+    let materials: Vec<_> = registry.iter().collect();
+    out.extend_from_slice(&(materials.len() as u16).to_le_bytes());
+    for (_, definition) in &materials {
+        let name = definition.name.as_bytes();
+        out.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        out.extend_from_slice(name);
+    }
+
+    write_runs(&mut out, &grid.particles, |particle, out| {
+        let (r, g, b) = particle.get_color();
+        out.extend_from_slice(&[particle.material_type, (particle.flags & RUNTIME_FLAGS).bits(), r, g, b]);
+    });
+    write_runs(&mut out, &grid.velocities, |velocity, out| {
+        out.extend_from_slice(&velocity.x.to_le_bytes());
+        out.extend_from_slice(&velocity.y.to_le_bytes());
+    });
+    write_runs(&mut out, &grid.temperatures, |temperature, out| {
+        out.extend_from_slice(&temperature.to_le_bytes());
+    });
+    write_runs(&mut out, &grid.fuel, |fuel, out| out.push(*fuel));
+
+    let checksum = fnv1a(&out);
+    out.extend_from_slice(&checksum.to_le_bytes());
+    out
+}
+
+/// Rebuilds a grid from the save file format, remapping materials by name
+pub fn decode(bytes: &[u8], registry: &MaterialRegistry) -> Result<Grid, SaveError> {
+    if bytes.len() < MAGIC.len() || &bytes[..MAGIC.len()] != MAGIC {
+        return Err(SaveError::NotASaveFile);
+    }
+    let mut reader = Reader { bytes, pos: MAGIC.len() };
+
+    let version = reader.u16()?;
+    if version == 0 {
+        return Err(SaveError::Corrupt("version 0 was never written".into()));
+    }
+    if version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion { found: version, supported: FORMAT_VERSION });
+    }
+
+    // The loaded grid takes the size it was saved at
+    let width = reader.u32()? as usize;
+    let height = reader.u32()? as usize;
+    if width == 0 || height == 0 || width > MAX_GRID_SIZE || height > MAX_GRID_SIZE {
+        return Err(SaveError::Corrupt(format!("grid size {width}x{height} is not supported")));
+    }
+
+    let count = reader.u16()?;
+    let mut names = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let len = reader.u16()?;
+        let name = std::str::from_utf8(reader.take(len as usize)?)
+            .map_err(|_| SaveError::Corrupt("material name is not valid UTF-8".into()))?;
+        names.push(name);
+    }
+
+    let cells = width * height;
+    let particles = read_runs(&mut reader, cells, 5, |record| {
+        if record[0] as usize >= names.len() {
+            return Err(SaveError::Corrupt(format!("material index {} is not in the table", record[0])));
+        }
+        Ok(<[u8; 5]>::try_from(record).unwrap())
+    })?;
+    let velocities = read_runs(&mut reader, cells, 8, |record| {
+        Ok(Vec2::new(f32_at(record, 0), f32_at(record, 4)))
+    })?;
+    let temperatures = read_runs(&mut reader, cells, 4, |record| Ok(f32_at(record, 0)))?;
+    let fuel = read_runs(&mut reader, cells, 1, |record| Ok(record[0]))?;
+
+    // Only trust the contents once the whole file checks out
+    let checksum = fnv1a(&bytes[..reader.pos]);
+    if reader.u32()? != checksum {
+        return Err(SaveError::Corrupt("checksum mismatch".into()));
+    }
+    if reader.pos != bytes.len() {
+        return Err(SaveError::Corrupt("unexpected data after the checksum".into()));
+    }
+
+    let materials = names
+        .into_iter()
+        .map(|name| registry.find(name).ok_or_else(|| SaveError::UnknownMaterial(name.to_string())))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut grid = Grid::new(registry.clone(), width, height);
+    grid.particles = particles
+        .into_iter()
+        .map(|[index, flags, r, g, b]| {
+            let mut particle = registry.particle(materials[index as usize]);
+            if version >= 2 {
+                particle = particle.with_color(r, g, b);
+            }
+            particle.flags |= ParticleFlags::from_bits_truncate(flags) & RUNTIME_FLAGS;
+            particle
+        })
+        .collect();
+    grid.velocities = velocities;
+    grid.temperatures = temperatures;
+    grid.fuel = fuel;
+
+    grid.wake_all();
+    Ok(grid)
+}
+
+/// Writes `values` as runs of identical records
+fn write_runs<T: PartialEq>(out: &mut Vec<u8>, values: &[T], mut write: impl FnMut(&T, &mut Vec<u8>)) {
+    let mut start = 0;
+    while start < values.len() {
+        let mut end = start + 1;
+        while end < values.len() && values[end] == values[start] {
+            end += 1;
+        }
+        write_varint(out, (end - start) as u32);
+        write(&values[start], out);
+        start = end;
+    }
+}
+
+/// Reads runs of `record_len`-byte records until `cells` values were produced
+fn read_runs<T: Clone>(
+    reader: &mut Reader,
+    cells: usize,
+    record_len: usize,
+    mut read: impl FnMut(&[u8]) -> Result<T, SaveError>,
+) -> Result<Vec<T>, SaveError> {
+    let mut values = Vec::with_capacity(cells);
+    while values.len() < cells {
+        let run = reader.varint()? as usize;
+        if run == 0 || run > cells - values.len() {
+            return Err(SaveError::Corrupt(format!("run of {run} cells doesn't fit the grid")));
+        }
+        let value = read(reader.take(record_len)?)?;
+        values.extend(std::iter::repeat_n(value, run));
+    }
+    Ok(values)
+}
+
+fn write_varint(out: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn f32_at(record: &[u8], offset: usize) -> f32 {
+    f32::from_le_bytes(record[offset..offset + 4].try_into().unwrap())
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &byte| (hash ^ byte as u32).wrapping_mul(0x0100_0193))
+}
+
+/// Cursor over the bytes of a save file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], SaveError> {
+        let slice = self.bytes.get(self.pos..self.pos + len).ok_or(SaveError::Truncated)?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u16(&mut self) -> Result<u16, SaveError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> Result<u32, SaveError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn varint(&mut self) -> Result<u32, SaveError> {
+        let mut value = 0u32;
+        for shift in (0..32).step_by(7) {
+            let byte = self.take(1)?[0];
+            value |= ((byte & 0x7f) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(SaveError::Corrupt("run length is too long".into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grid() -> Grid {
+        let registry = MaterialRegistry::builtin();
+        let mut grid = Grid::new(registry.clone(), 40, 30);
+        for x in 5..35 {
+            grid.spawn(x, 20, registry.sand());
+            grid.set_velocity(x, 20, Vec2::new(x as f32 * 0.5, -1.0));
+        }
+        let wood = registry.find("Wood").unwrap();
+        grid.spawn(10, 10, wood);
+        let mut burning = grid.get(10, 10);
+        burning.flags |= ParticleFlags::BURNING;
+        grid.set_particle(10, 10, burning);
+        grid.set_fuel(10, 10, 42);
+        grid.set_temperature(3, 4, 512.5);
+        grid
+    }
+
+    #[test]
+    fn decoding_restores_the_encoded_grid() {
+        let grid = grid();
+        let decoded = decode(&encode(&grid), grid.registry()).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (grid.width(), grid.height()));
+        assert!(decoded.particles == grid.particles);
+        assert!(decoded.velocities == grid.velocities);
+        assert!(decoded.temperatures == grid.temperatures);
+        assert!(decoded.fuel == grid.fuel);
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let grid = grid();
+        let bytes = encode(&grid);
+        for len in MAGIC.len()..bytes.len() {
+            let result = decode(&bytes[..len], grid.registry());
+            assert!(matches!(result, Err(SaveError::Truncated)), "{len} bytes");
+        }
+    }
+
+    #[test]
+    fn files_without_the_signature_are_rejected() {
+        let grid = grid();
+        let mut bytes = encode(&grid);
+        bytes[0] = b'X';
+        assert!(matches!(decode(&bytes, grid.registry()), Err(SaveError::NotASaveFile)));
+        assert!(matches!(decode(b"SAND", grid.registry()), Err(SaveError::NotASaveFile)));
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let grid = grid();
+        let mut bytes = encode(&grid);
+        bytes[MAGIC.len()..MAGIC.len() + 2].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            decode(&bytes, grid.registry()),
+            Err(SaveError::UnsupportedVersion { found, supported: FORMAT_VERSION }) if found == FORMAT_VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn damaged_contents_fail_the_checksum() {
+        let grid = grid();
+        let mut bytes = encode(&grid);
+        // The last byte before the checksum is the fuel of the final run
+        let last = bytes.len() - 5;
+        bytes[last] ^= 1;
+        assert!(matches!(decode(&bytes, grid.registry()), Err(SaveError::Corrupt(reason)) if reason == "checksum mismatch"));
+    }
+
+    #[test]
+    fn overlong_run_lengths_are_rejected() {
+        let registry = MaterialRegistry::builtin();
+        let grid = Grid::new(registry.clone(), 16, 16);
+        let bytes = encode(&grid);
+        // The first run covers all 256 empty cells, its length takes two bytes
+        let header = MAGIC.len() + 2 + 4 + 4 + 2 + registry.iter().map(|(_, definition)| 2 + definition.name.len()).sum::<usize>();
+        assert_eq!(bytes[header..header + 2], [0x80, 0x02]);
+        let mut damaged = bytes[..header].to_vec();
+        damaged.extend_from_slice(&[0xff; 5]);
+        damaged.extend_from_slice(&bytes[header + 2..]);
+        assert!(matches!(decode(&damaged, grid.registry()), Err(SaveError::Corrupt(reason)) if reason == "run length is too long"));
+    }
+}
@@ -0,0 +1,24 @@
+mod format;
+
+use std::path::Path;
+use crate::grid::Grid;
+use crate::registry::MaterialRegistry;
+
+pub use format::SaveError;
+
+/// Writes the grid to a save file, creating its directory if needed
+pub fn save_grid(grid: &Grid, path: impl AsRef<Path>) -> Result<(), SaveError> {
+    let path = path.as_ref();
+    let io_error = |source| SaveError::Io { path: path.to_path_buf(), source };
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io_error)?;
+    }
+    std::fs::write(path, format::encode(grid)).map_err(io_error)
+}
+
+/// Reads a grid from a save file, mapping its materials onto `registry` by name
+pub fn load_grid(path: impl AsRef<Path>, registry: &MaterialRegistry) -> Result<Grid, SaveError> {
+    let path = path.as_ref();
+    let bytes = std::fs::read(path).map_err(|source| SaveError::Io { path: path.to_path_buf(), source })?;
+    format::decode(&bytes, registry)
+}
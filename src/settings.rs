@@ -0,0 +1,222 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use bevy::prelude::*;
+use serde::Deserialize;
+use crate::config::{
+    DEFAULT_CELL_SIZE, DEFAULT_GRID_HEIGHT, DEFAULT_GRID_WIDTH, DEFAULT_TICKS_PER_SECOND, MAX_GRID_SIZE,
+    MAX_TICKS_PER_SECOND, MIN_GRID_SIZE, MIN_TICKS_PER_SECOND, SETTINGS_PATH, UI_SIDEBAR_WIDTH,
+};
+
+const USAGE: &str = "\
+Options:
+  --width <CELLS>       grid width in cells
+  --height <CELLS>      grid height in cells
+  --cell-size <PIXELS>  on-screen size of a cell
+  --ticks-per-second <TICKS>
+                        simulation rate at normal speed
+  --config <PATH>       settings file to read instead of settings.ron
+  --help                print this message";
+
+/// Startup settings: the grid size the world starts with, how large cells are drawn
+/// and how fast the simulation runs.
+/// Read from the settings file, then overridden by command-line arguments.
+#[derive(Resource, Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub width: usize,
+    pub height: usize,
+    pub cell_size: f32,
+    pub ticks_per_second: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_GRID_WIDTH,
+            height: DEFAULT_GRID_HEIGHT,
+            cell_size: DEFAULT_CELL_SIZE,
+            ticks_per_second: DEFAULT_TICKS_PER_SECOND,
+        }
+    }
+}
+
+/// Errors produced while reading settings
+#[derive(Debug)]
+pub enum SettingsError {
+    /// The settings file couldn't be read
+    Io { path: PathBuf, source: std::io::Error },
+    /// The settings file isn't valid RON or doesn't match the expected layout
+    Parse(ron::error::SpannedError),
+    /// A command-line argument is unknown or lacks a valid value
+    Argument(String),
+    /// A value is outside the range the simulation supports
+    OutOfRange(String),
+}
+
+impl fmt::Display for SettingsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SettingsError::Io { path, source } => write!(f, "could not read {}: {source}", path.display()),
+            SettingsError::Parse(err) => write!(f, "invalid settings: {err}"),
+            SettingsError::Argument(reason) => write!(f, "{reason}"),
+            SettingsError::OutOfRange(reason) => write!(f, "{reason}"),
+        }
+    }
+}
+
+impl std::error::Error for SettingsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SettingsError::Io { source, .. } => Some(source),
+            SettingsError::Parse(err) => Some(err),
+            SettingsError::Argument(_) | SettingsError::OutOfRange(_) => None,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads the settings for this run from the process arguments, exiting with
+    /// a usage message when they are invalid. A missing default settings file is fine.
+    pub fn from_env() -> Self {
+        let args: Vec<String> = std::env::args().skip(1).collect();
+        if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+            println!("{USAGE}");
+            std::process::exit(0);
+        }
+        Self::from_args(&args).unwrap_or_else(|err| {
+            eprintln!("error: {err}\n\n{USAGE}");
+            std::process::exit(2);
+        })
+    }
+
+    /// Builds the settings from command-line arguments (without the program name),
+    /// on top of the settings file they point to
+    pub fn from_args(args: &[String]) -> Result<Self, SettingsError> {
+        let mut config_path = None;
+        let mut overrides = Vec::new();
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let value = args.next().ok_or_else(|| SettingsError::Argument(format!("{arg} needs a value")))?;
+            match arg.as_str() {
+                "--config" => config_path = Some(PathBuf::from(value)),
+                "--width" | "--height" | "--cell-size" | "--ticks-per-second" => overrides.push((arg.as_str(), value.as_str())),
+                _ => return Err(SettingsError::Argument(format!("unknown argument {arg}"))),
+            }
+        }
+
+        let mut settings = match config_path {
+            Some(path) => Self::load(path)?,
+            None if Path::new(SETTINGS_PATH).exists() => Self::load(SETTINGS_PATH)?,
+            None => Self::default(),
+        };
+        for (arg, value) in overrides {
+            let invalid = || SettingsError::Argument(format!("invalid value for {arg}: {value}"));
+            match arg {
+                "--width" => settings.width = value.parse().map_err(|_| invalid())?,
+                "--height" => settings.height = value.parse().map_err(|_| invalid())?,
+                "--cell-size" => settings.cell_size = value.parse().map_err(|_| invalid())?,
+                _ => settings.ticks_per_second = value.parse().map_err(|_| invalid())?,
+            }
+        }
+
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Reads settings from a RON file; fields it leaves out keep their defaults
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, SettingsError> {
+        let path = path.as_ref();
+        let source = std::fs::read_to_string(path)
+            .map_err(|source| SettingsError::Io { path: path.to_path_buf(), source })?;
+        Self::from_ron(&source)
+    }
+
+    /// Parses settings from a RON string; fields it leaves out keep their defaults
+    pub fn from_ron(source: &str) -> Result<Self, SettingsError> {
+        ron::from_str(source).map_err(SettingsError::Parse)
+    }
+
+    fn validate(&self) -> Result<(), SettingsError> {
+        for (name, size) in [("width", self.width), ("height", self.height)] {
+            if !(MIN_GRID_SIZE..=MAX_GRID_SIZE).contains(&size) {
+                return Err(SettingsError::OutOfRange(format!(
+                    "grid {name} {size} is outside {MIN_GRID_SIZE}..={MAX_GRID_SIZE}"
+                )));
+            }
+        }
+        if !(self.cell_size >= 1.0 && self.cell_size.is_finite()) {
+            return Err(SettingsError::OutOfRange(format!("cell size {} is below 1", self.cell_size)));
+        }
+        if !(MIN_TICKS_PER_SECOND..=MAX_TICKS_PER_SECOND).contains(&self.ticks_per_second) {
+            return Err(SettingsError::OutOfRange(format!(
+                "{} ticks per second is outside {MIN_TICKS_PER_SECOND}..={MAX_TICKS_PER_SECOND}",
+                self.ticks_per_second
+            )));
+        }
+        Ok(())
+    }
+
+    /// Initial size of the window, showing the whole grid next to the sidebar
+    pub fn window_size(&self) -> Vec2 {
+        Vec2::new(self.width as f32 * self.cell_size + UI_SIDEBAR_WIDTH, self.height as f32 * self.cell_size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Arguments read on top of a settings file holding `contents`, named after the test
+    fn from_file_and_args(test: &str, contents: &str, args: &[&str]) -> Result<Settings, SettingsError> {
+        let path = std::env::temp_dir().join(format!("{test}-{}.ron", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let mut all = vec!["--config".to_string(), path.display().to_string()];
+        all.extend(args.iter().map(|arg| arg.to_string()));
+        let settings = Settings::from_args(&all);
+        std::fs::remove_file(&path).unwrap();
+        settings
+    }
+
+    #[test]
+    fn fields_left_out_keep_their_defaults() {
+        let settings = Settings::from_ron("(width: 320, ticks_per_second: 30.0)").unwrap();
+        assert_eq!(settings, Settings { width: 320, ticks_per_second: 30.0, ..Settings::default() });
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        assert!(matches!(Settings::from_ron("(depth: 3)"), Err(SettingsError::Parse(_))));
+    }
+
+    #[test]
+    fn arguments_override_the_settings_file() {
+        let settings = from_file_and_args(
+            "arguments_override_the_settings_file",
+            "(width: 320, height: 180)",
+            &["--height", "200", "--cell-size", "2.5", "--ticks-per-second", "120"],
+        )
+        .unwrap();
+        assert_eq!((settings.width, settings.height), (320, 200));
+        assert_eq!(settings.cell_size, 2.5);
+        assert_eq!(settings.ticks_per_second, 120.0);
+    }
+
+    #[test]
+    fn bad_arguments_are_rejected() {
+        let parse = |args: &[&str]| from_file_and_args("bad_arguments_are_rejected", "()", args);
+        assert!(matches!(parse(&["--depth", "3"]), Err(SettingsError::Argument(reason)) if reason == "unknown argument --depth"));
+        assert!(matches!(parse(&["--width"]), Err(SettingsError::Argument(reason)) if reason == "--width needs a value"));
+        assert!(matches!(parse(&["--width", "wide"]), Err(SettingsError::Argument(reason)) if reason == "invalid value for --width: wide"));
+    }
+
+    #[test]
+    fn values_out_of_range_are_rejected() {
+        let parse = |args: &[&str]| from_file_and_args("values_out_of_range_are_rejected", "(cell_size: 2.0)", args);
+        assert!(matches!(parse(&["--width", "8"]), Err(SettingsError::OutOfRange(_))));
+        assert!(matches!(parse(&["--height", "100000"]), Err(SettingsError::OutOfRange(_))));
+        assert!(matches!(parse(&["--cell-size", "0.5"]), Err(SettingsError::OutOfRange(_))));
+        assert!(matches!(parse(&["--cell-size", "NaN"]), Err(SettingsError::OutOfRange(_))));
+        assert!(matches!(parse(&["--ticks-per-second", "0"]), Err(SettingsError::OutOfRange(_))));
+        assert!(parse(&[]).is_ok());
+    }
+}
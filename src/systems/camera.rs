@@ -0,0 +1,61 @@
+use bevy::prelude::*;
+use bevy::render::camera::Viewport;
+use bevy::window::PrimaryWindow;
+use crate::config::{CAMERA_MAX_SCALE, CAMERA_MIN_SCALE, UI_SIDEBAR_WIDTH};
+
+/// Width of the sidebar in logical pixels, measured by the UI each frame
+#[derive(Resource)]
+pub struct SidebarWidth(pub f32);
+
+impl Default for SidebarWidth {
+    fn default() -> Self {
+        Self(UI_SIDEBAR_WIDTH)
+    }
+}
+
+/// Restricts the camera to the part of the window left of the sidebar, so the
+/// grid is centred in the space that is actually visible
+pub fn fit_camera_viewport(
+    window_query: Query<&Window, With<PrimaryWindow>>,
+    sidebar: Res<SidebarWidth>,
+    mut camera_q: Query<&mut Camera>,
+) {
+    let Ok(window) = window_query.get_single() else { return };
+    let mut camera = camera_q.single_mut();
+    let sidebar = (sidebar.0 * window.scale_factor()).round() as u32;
+    let size = UVec2::new(window.physical_width().saturating_sub(sidebar), window.physical_height());
+
+    // A minimized window leaves nothing to draw into, fall back to the whole window
+    let viewport = (size.x > 0 && size.y > 0).then(|| Viewport { physical_size: size, ..default() });
+    if camera.viewport.as_ref().map(|v| v.physical_size) != viewport.as_ref().map(|v| v.physical_size) {
+        camera.viewport = viewport;
+    }
+}
+
+/// Zooms the camera by `factor` (above 1 zooms out), keeping the world point
+/// `anchor` at the same place on screen
+pub fn zoom_camera(projection: &mut OrthographicProjection, transform: &mut Transform, factor: f32, anchor: Vec2) {
+    let scale = (projection.scale * factor).clamp(CAMERA_MIN_SCALE, CAMERA_MAX_SCALE);
+    let ratio = scale / projection.scale;
+    let position = transform.translation.truncate();
+    transform.translation = (anchor - (anchor - position) * ratio).extend(transform.translation.z);
+    projection.scale = scale;
+}
+
+/// Centres the camera on the grid and zooms so all of its `grid_size` pixels fit the viewport
+pub fn fit_camera_to_grid(
+    projection: &mut OrthographicProjection,
+    transform: &mut Transform,
+    grid_size: Vec2,
+    viewport_size: Vec2,
+    cell_size: f32,
+) {
+    let scale = (grid_size / viewport_size).max_element();
+    // Prefer a whole number of screen pixels per cell, so every cell is drawn the same size
+    let cell_pixels = (cell_size / scale).floor();
+    let scale = if cell_pixels >= 1.0 { cell_size / cell_pixels } else { scale };
+
+    projection.scale = scale.clamp(CAMERA_MIN_SCALE, CAMERA_MAX_SCALE);
+    transform.translation.x = 0.0;
+    transform.translation.y = 0.0;
+}
@@ -1,7 +1,11 @@
 mod setup;
+mod camera;
 mod update;
 mod render;
+mod record;
 
-pub use setup::{setup, SimulationTexture};
-pub use update::update_grid;
-pub use render::render_grid;
\ No newline at end of file
+pub use camera::{fit_camera_to_grid, fit_camera_viewport, zoom_camera, SidebarWidth};
+pub use setup::{fit_view_to_grid, setup, SimulationTexture};
+pub use update::{update_grid, ParallelUpdate, SimulationControl, TickTime};
+pub use render::{extract_texture_update, render_grid, write_texture_regions, DebugView, PendingTextureWrites, RenderMode, TextureUpdate};
+pub use record::{finish_recording_on_exit, record_frame, Recording};
@@ -0,0 +1,129 @@
+use std::path::Path;
+use std::time::Duration;
+use bevy::prelude::*;
+use crate::config::{DEFAULT_RECORDING_PATH, DEFAULT_RECORDING_SECONDS};
+use crate::grid::Grid;
+use crate::image_io::{grid_to_image, ExportScale, RecordFormat, Recorder};
+use crate::settings::Settings;
+use super::DebugView;
+
+/// Recording settings picked in the sidebar, and the recording in progress
+#[derive(Resource)]
+pub struct Recording {
+    pub path: String,
+    pub format: RecordFormat,
+    pub scale: ExportScale,
+    pub frame_skip: u32,   // Rendered frames skipped between captured ones
+    pub max_seconds: u32,  // Recording stops on its own after this long
+    pub status: Option<Result<String, String>>,
+    active: Option<ActiveRecording>,
+}
+
+struct ActiveRecording {
+    recorder: Recorder,
+    elapsed: Duration,
+    frame: u32,
+    size: Option<(usize, usize)>, // Grid size of the first captured frame
+}
+
+impl Default for Recording {
+    fn default() -> Self {
+        Self {
+            path: DEFAULT_RECORDING_PATH.to_string(),
+            format: RecordFormat::default(),
+            scale: ExportScale::default(),
+            frame_skip: 0,
+            max_seconds: DEFAULT_RECORDING_SECONDS,
+            status: None,
+            active: None,
+        }
+    }
+}
+
+impl Recording {
+    pub fn is_recording(&self) -> bool {
+        self.active.is_some()
+    }
+
+    /// Frames captured, frames dropped and time passed since the recording started
+    pub fn progress(&self) -> Option<(usize, usize, Duration)> {
+        self.active.as_ref().map(|active| (active.recorder.frames(), active.recorder.dropped(), active.elapsed))
+    }
+
+    pub fn start(&mut self) {
+        match Recorder::start(&self.path, self.format) {
+            Ok(recorder) => {
+                self.active = Some(ActiveRecording { recorder, elapsed: Duration::ZERO, frame: 0, size: None });
+                self.status = None;
+            }
+            Err(err) => self.status = Some(Err(format!("Recording failed: {err}"))),
+        }
+    }
+
+    /// Stops recording once every captured frame is written
+    pub fn stop(&mut self) {
+        if let Some(active) = self.active.take() {
+            let dropped = active.recorder.dropped();
+            self.status = Some(match active.recorder.finish() {
+                Ok(frames) => {
+                    let output = self.format.output_path(Path::new(&self.path));
+                    match dropped {
+                        0 => Ok(format!("Recorded {frames} frames to {}", output.display())),
+                        _ => Ok(format!("Recorded {frames} frames to {}, {dropped} dropped", output.display())),
+                    }
+                }
+                Err(err) => Err(format!("Recording failed: {err}")),
+            });
+        }
+    }
+}
+
+/// Captures the grid as currently shown, debug view included, into the active recording, if any
+pub fn record_frame(
+    grid: Res<Grid>,
+    settings: Res<Settings>,
+    debug_view: Res<DebugView>,
+    time: Res<Time>,
+    mut recording: ResMut<Recording>,
+) {
+    let (frame_skip, max_duration, scale) = (
+        recording.frame_skip,
+        Duration::from_secs(recording.max_seconds as u64),
+        recording.scale,
+    );
+    let Some(active) = recording.active.as_mut() else {
+        return;
+    };
+
+    active.elapsed += time.delta();
+    if active.elapsed > max_duration {
+        recording.stop();
+        return;
+    }
+
+    active.frame += 1;
+    if (active.frame - 1) % (frame_skip + 1) != 0 {
+        return;
+    }
+
+    // Every frame of a recording has the same size, so resizing the grid ends it
+    let size = (grid.width(), grid.height());
+    if *active.size.get_or_insert(size) != size {
+        recording.stop();
+        return;
+    }
+
+    // Play back at the speed it was recorded
+    let delay = time.delta() * (frame_skip + 1);
+    let frame = grid_to_image(&grid, scale.factor(settings.cell_size), |grid, x, y| debug_view.mode.color(grid, x, y));
+    if !active.recorder.push(frame, delay) {
+        recording.stop();
+    }
+}
+
+/// Finishes the recording in progress when the app closes, so the file isn't left incomplete
+pub fn finish_recording_on_exit(mut exit: EventReader<AppExit>, mut recording: ResMut<Recording>) {
+    if exit.read().next().is_some() {
+        recording.stop();
+    }
+}
@@ -1,26 +1,216 @@
 use bevy::prelude::*;
-use crate::config;
+use bevy::render::render_asset::RenderAssets;
+use bevy::render::render_resource::{Extent3d, ImageCopyTexture, ImageDataLayout, Origin3d, TextureAspect};
+use bevy::render::renderer::RenderQueue;
+use bevy::render::texture::GpuImage;
+use bevy::render::Extract;
+use crate::config::CHUNK_SIZE;
 use crate::grid::Grid;
+use crate::materials::MAX_REACH;
+use crate::materials::properties::MAX_DENSITY;
 use super::SimulationTexture;
-use crate::materials::Material;
 
+const MAP_MIN_TEMPERATURE: f32 = -50.0; // Coldest temperature told apart in the temperature map
+const MAP_MAX_TEMPERATURE: f32 = 1200.0; // Hottest temperature told apart in the temperature map
+const CHUNK_BORDER_COLOR: (u8, u8, u8) = (255, 0, 255);
+
+/// A rectangle of the grid texture to overwrite, one texel per cell
+#[derive(Clone)]
+pub struct TextureRegion {
+    pub rect: URect,
+    data: Vec<u8>, // RGBA texels of the rectangle, row by row
+}
+
+/// The regions of the grid texture redrawn this frame. They are written straight
+/// into the GPU texture by the render world: changing the image asset instead
+/// would upload the whole texture again.
+#[derive(Resource, Default)]
+pub struct TextureUpdate {
+    pub regions: Vec<TextureRegion>,
+    size: UVec2, // Size of the grid the regions were drawn from
+}
+
+/// What the grid texture shows: the materials, or one of the per-cell debug maps
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    #[default]
+    Materials,
+    /// Speed of each particle, from still (black) to `MAX_REACH` cells per tick (white)
+    Velocity,
+    /// Density of each particle, from empty (black) to the densest material (white)
+    Density,
+    /// Temperature of every cell, air included
+    Temperature,
+    /// Particles that moved on the latest tick in white, the others in grey and those in sleeping chunks in blue
+    Motion,
+    /// The materials, dimmed in sleeping chunks, with the chunk borders drawn over them
+    Chunks,
+}
+
+impl RenderMode {
+    pub const ALL: [RenderMode; 6] = [
+        RenderMode::Materials,
+        RenderMode::Velocity,
+        RenderMode::Density,
+        RenderMode::Temperature,
+        RenderMode::Motion,
+        RenderMode::Chunks,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            RenderMode::Materials => "Materials",
+            RenderMode::Velocity => "Velocity",
+            RenderMode::Density => "Density",
+            RenderMode::Temperature => "Temperature",
+            RenderMode::Motion => "Settled / Moving",
+            RenderMode::Chunks => "Chunks",
+        }
+    }
+
+    /// Whether the view depends on more than the particles, which are all the
+    /// grid tracks changes of, so it has to be redrawn in full every frame
+    fn redraws_every_frame(self) -> bool {
+        !matches!(self, RenderMode::Materials | RenderMode::Density)
+    }
+
+    /// The colour the cell at (x, y) is drawn with in this mode
+    pub fn color(self, grid: &Grid, x: usize, y: usize) -> (u8, u8, u8) {
+        let particle = grid.get(x, y);
+        let awake = grid.is_chunk_active(x / CHUNK_SIZE, y / CHUNK_SIZE);
+        match self {
+            RenderMode::Materials => grid.color(x, y),
+            RenderMode::Velocity => heat_color(grid.get_velocity(x, y).length() / MAX_REACH as f32),
+            RenderMode::Density => heat_color(particle.get_density() * 10.0 / MAX_DENSITY as f32),
+            RenderMode::Temperature => heat_color(
+                (grid.get_temperature(x, y) - MAP_MIN_TEMPERATURE) / (MAP_MAX_TEMPERATURE - MAP_MIN_TEMPERATURE),
+            ),
+            RenderMode::Motion => match (particle.material(), awake) {
+                (material, _) if material == grid.registry().empty() => (0, 0, 0),
+                (_, false) => (40, 60, 140),
+                _ if grid.moved_last_tick(x, y) => (255, 255, 255),
+                _ => (110, 110, 110),
+            },
+            RenderMode::Chunks => {
+                if x.is_multiple_of(CHUNK_SIZE) || y.is_multiple_of(CHUNK_SIZE) {
+                    return CHUNK_BORDER_COLOR;
+                }
+                let (r, g, b) = grid.color(x, y);
+                if awake { (r, g, b) } else { (r / 3, g / 3, b / 3) }
+            }
+        }
+    }
+}
+
+/// Maps 0.0-1.0 onto black, blue, red, yellow and white
+fn heat_color(value: f32) -> (u8, u8, u8) {
+    const STOPS: [(f32, f32, f32); 5] = [
+        (0.0, 0.0, 0.0),
+        (0.0, 0.0, 1.0),
+        (1.0, 0.0, 0.0),
+        (1.0, 1.0, 0.0),
+        (1.0, 1.0, 1.0),
+    ];
+    let position = value.clamp(0.0, 1.0) * (STOPS.len() - 1) as f32;
+    let index = (position as usize).min(STOPS.len() - 2);
+    let t = position - index as f32;
+    let (from, to) = (STOPS[index], STOPS[index + 1]);
+    let channel = |a: f32, b: f32| ((a + (b - a) * t) * 255.0).round() as u8;
+    (channel(from.0, to.0), channel(from.1, to.1), channel(from.2, to.2))
+}
+
+/// Debugging aids for the renderer, picked from the sidebar
+#[derive(Resource, Default)]
+pub struct DebugView {
+    pub mode: RenderMode,
+    /// Outlines the regions redrawn each frame
+    pub show_dirty_regions: bool,
+}
+
+/// Redraws the parts of the grid whose colours changed since the last frame,
+/// or all of it for render modes showing state the grid doesn't track changes of
 pub fn render_grid(
-    grid: Res<Grid>,
-    simulation_texture: Res<SimulationTexture>,
-    mut images: ResMut<Assets<Image>>,
+    mut grid: ResMut<Grid>,
+    mut update: ResMut<TextureUpdate>,
+    debug_view: Res<DebugView>,
+    mut last_mode: Local<RenderMode>,
 ) {
-    if let Some(image) = images.get_mut(&simulation_texture.image_handle) {
-        for y in 0..config::GRID_HEIGHT {
-            for x in 0..config::GRID_WIDTH {
-                let material = Material::from_id(grid.get(x, y).material_type);
-                let properties = material.properties();
-                let pixel_index = (y * config::GRID_WIDTH + x) * 4;
-                
-                image.data[pixel_index] = properties.color.0;     // R
-                image.data[pixel_index + 1] = properties.color.1; // G
-                image.data[pixel_index + 2] = properties.color.2; // B
-                image.data[pixel_index + 3] = 255;               // A
+    let mode = debug_view.mode;
+    if mode != *last_mode {
+        grid.mark_all_dirty();
+        *last_mode = mode;
+    }
+
+    update.regions.clear();
+    update.size = UVec2::new(grid.width() as u32, grid.height() as u32);
+    let mut dirty_regions = grid.take_dirty_regions();
+    if mode.redraws_every_frame() {
+        dirty_regions = vec![URect::from_corners(UVec2::ZERO, update.size)];
+    }
+
+    for rect in dirty_regions {
+        let mut data = Vec::with_capacity((rect.width() * rect.height()) as usize * 4);
+        for y in rect.min.y..rect.max.y {
+            for x in rect.min.x..rect.max.x {
+                let (r, g, b) = mode.color(&grid, x as usize, y as usize);
+                data.extend_from_slice(&[r, g, b, 255]);
             }
         }
+        update.regions.push(TextureRegion { rect, data });
+    }
+}
+
+/// Texture regions waiting in the render world for the GPU texture they belong to
+#[derive(Resource, Default)]
+pub struct PendingTextureWrites {
+    image: Option<AssetId<Image>>,
+    size: UVec2,
+    regions: Vec<TextureRegion>,
+}
+
+/// Hands this frame's redrawn regions over to the render world
+pub fn extract_texture_update(
+    update: Extract<Res<TextureUpdate>>,
+    simulation_texture: Extract<Option<Res<SimulationTexture>>>,
+    mut pending: ResMut<PendingTextureWrites>,
+) {
+    let Some(simulation_texture) = simulation_texture.as_ref() else { return };
+    // Regions of a grid that was since replaced by one of another size would land in the wrong place
+    if pending.size != update.size {
+        pending.regions.clear();
+        pending.size = update.size;
+    }
+    pending.image = Some(simulation_texture.image_handle.id());
+    pending.regions.extend(update.regions.iter().cloned());
+}
+
+/// Writes the pending regions into the GPU texture, once it exists at the grid's size
+pub fn write_texture_regions(
+    mut pending: ResMut<PendingTextureWrites>,
+    images: Res<RenderAssets<GpuImage>>,
+    queue: Res<RenderQueue>,
+) {
+    let Some(gpu_image) = pending.image.and_then(|id| images.get(id)) else { return };
+    // The texture is uploaded again after a resize, keep the regions until then
+    if gpu_image.size != pending.size {
+        return;
+    }
+    for region in pending.regions.drain(..) {
+        let rect = region.rect;
+        queue.write_texture(
+            ImageCopyTexture {
+                texture: &gpu_image.texture,
+                mip_level: 0,
+                origin: Origin3d { x: rect.min.x, y: rect.min.y, z: 0 },
+                aspect: TextureAspect::All,
+            },
+            &region.data,
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(rect.width() * 4),
+                rows_per_image: None,
+            },
+            Extent3d { width: rect.width(), height: rect.height(), depth_or_array_layers: 1 },
+        );
     }
 }
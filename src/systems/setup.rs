@@ -1,40 +1,48 @@
 use bevy::prelude::*;
 use bevy::asset::RenderAssetUsages;
 use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
-use bevy::sprite::Anchor;
-use bevy::window::PrimaryWindow;
-use crate::config::*;
+use crate::grid::Grid;
+use crate::settings::Settings;
+use super::camera::fit_camera_to_grid;
 
 #[derive(Resource)]
 pub struct SimulationTexture {
     pub(crate) image_handle: Handle<Image>,
 }
 
+/// Marks the sprite the grid texture is drawn on
+#[derive(Component)]
+pub struct GridSprite;
+
 pub fn setup(
     mut commands: Commands,
     mut images: ResMut<Assets<Image>>,
-    window_query: Query<&Window, With<PrimaryWindow>>,
+    grid: Res<Grid>,
+    settings: Res<Settings>,
 ) {
     spawn_camera(&mut commands);
-    let image_handle = create_grid_texture(&mut images);
-    spawn_grid_sprite(&mut commands, &window_query, image_handle.clone());
+    let image_handle = create_grid_texture(&mut images, &grid);
+    spawn_grid_sprite(&mut commands, image_handle.clone(), &grid, &settings);
     commands.insert_resource(SimulationTexture { image_handle });
 }
 
 fn spawn_camera(commands: &mut Commands) {
-    commands.spawn(Camera2d::default());
+    commands.spawn(Camera2d);
 }
 
-fn create_grid_texture(images: &mut Assets<Image>) -> Handle<Image> {
-    let size = Extent3d {
-        width: GRID_WIDTH as u32,
-        height: GRID_HEIGHT as u32,
+fn texture_size(grid: &Grid) -> Extent3d {
+    Extent3d {
+        width: grid.width() as u32,
+        height: grid.height() as u32,
         depth_or_array_layers: 1,
-    };
+    }
+}
+
+fn create_grid_texture(images: &mut Assets<Image>, grid: &Grid) -> Handle<Image> {
     let mut image = Image::new(
-        size,
+        texture_size(grid),
         TextureDimension::D2,
-        vec![0; GRID_WIDTH * GRID_HEIGHT * 4],
+        vec![0; grid.width() * grid.height() * 4],
         TextureFormat::Rgba8UnormSrgb,
         RenderAssetUsages::all(),
     );
@@ -51,22 +59,55 @@ fn initialize_texture(image: &mut Image) {
     }
 }
 
-fn spawn_grid_sprite(
-    commands: &mut Commands,
-    window_query: &Query<&Window, With<PrimaryWindow>>,
-    image_handle: Handle<Image>,
-) {
-    let window = window_query.single();
+fn grid_sprite_size(grid: &Grid, settings: &Settings) -> Vec2 {
+    Vec2::new(
+        grid.width() as f32 * settings.cell_size,
+        grid.height() as f32 * settings.cell_size,
+    )
+}
+
+/// The grid is drawn centred on the world origin, with y pointing up the screen
+fn spawn_grid_sprite(commands: &mut Commands, image_handle: Handle<Image>, grid: &Grid, settings: &Settings) {
     commands.spawn((
         Sprite {
             image: image_handle,
-            custom_size: Some(Vec2::new(
-                (GRID_WIDTH as f32) * CELL_SIZE,
-                (GRID_HEIGHT as f32) * CELL_SIZE,
-            )),
-            anchor: Anchor::Custom(Vec2::new(-0.5, 0.0)),
+            custom_size: Some(grid_sprite_size(grid, settings)),
             ..Default::default()
         },
-        Transform::from_xyz(-window.width() / 2.0, 0.0, 0.0),
+        GridSprite,
     ));
-}
\ No newline at end of file
+}
+
+/// Resizes the texture and the sprite once the grid changed size, e.g. after a
+/// resize from the sidebar or loading a save of another size, and zooms the
+/// camera out to show the whole grid (also done once at startup)
+pub fn fit_view_to_grid(
+    mut grid: ResMut<Grid>,
+    settings: Res<Settings>,
+    simulation_texture: Res<SimulationTexture>,
+    mut images: ResMut<Assets<Image>>,
+    mut sprite_query: Query<&mut Sprite, With<GridSprite>>,
+    mut camera_q: Query<(&Camera, &mut Transform, &mut OrthographicProjection)>,
+    mut fitted_size: Local<Option<(usize, usize)>>,
+) {
+    // Only touch the image when it has to change, as that uploads all of it again
+    let size = texture_size(&grid);
+    let Some(current_size) = images.get(&simulation_texture.image_handle).map(|image| image.texture_descriptor.size) else { return };
+    if current_size != size {
+        let image = images.get_mut(&simulation_texture.image_handle).unwrap();
+        image.resize(size);
+        initialize_texture(image);
+        grid.mark_all_dirty();
+        sprite_query.single_mut().custom_size = Some(grid_sprite_size(&grid, &settings));
+    }
+
+    let grid_size = (grid.width(), grid.height());
+    if *fitted_size == Some(grid_size) {
+        return;
+    }
+    // The viewport is only known once the camera was rendered
+    let (camera, mut transform, mut projection) = camera_q.single_mut();
+    let Some(viewport_size) = camera.logical_viewport_size() else { return };
+    fit_camera_to_grid(&mut projection, &mut transform, grid_sprite_size(&grid, &settings), viewport_size, settings.cell_size);
+    *fitted_size = Some(grid_size);
+}
@@ -1,43 +1,253 @@
+use std::time::{Duration, Instant};
 use bevy::prelude::*;
-use rand::{prelude::*, thread_rng};
-use crate::config::{GRID_HEIGHT, GRID_WIDTH};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use crate::config::{CHUNK_SIZE, MAX_TICKS_PER_FRAME, MAX_TICKS_PER_SECOND, MIN_TICKS_PER_SECOND};
 use crate::grid::*;
-use crate::materials::MaterialBehavior;
-use crate::materials::Material;
-
-pub fn update_grid(mut grid: ResMut<Grid>) {
-    let mut new_grid = grid.clone();
-    
-    // Collect all non-empty cells
-    let mut particles: Vec<(usize, usize)> = (0..GRID_HEIGHT)
-        .flat_map(|y| (0..GRID_WIDTH).map(move |x| (x, y)))
-        .filter(|&(x, y)| grid.get(x, y).material_type != Material::Empty as u8)
-        .collect();
+use crate::materials::{conduct_heat, explode, MaterialBehavior, MAX_REACH};
+use crate::utils::rng::SimulationRng;
+
+// Chunks updated side by side are one chunk apart, so particles near the
+// facing edges of that chunk must never reach the same cell
+const _: () = assert!(2 * MAX_REACH < CHUNK_SIZE, "chunks are too small for parallel updates");
+
+/// Whether chunks are updated on several threads at once
+#[derive(Resource)]
+pub struct ParallelUpdate(pub bool);
+
+/// How long the last simulation tick took
+#[derive(Resource, Default)]
+pub struct TickTime(pub Duration);
+
+/// Pause, single-step and speed settings for the simulation, which runs at a
+/// fixed `ticks_per_second` whatever the frame rate
+#[derive(Resource)]
+pub struct SimulationControl {
+    pub paused: bool,
+    /// Ticks run per second at normal speed
+    pub ticks_per_second: f32,
+    /// Multiplier on the tick rate
+    pub speed: f32,
+    step_requested: bool,
+    accumulator: f32, // Ticks owed by the time elapsed so far, including a fraction carried over
+    ticks_run: u32, // Ticks run by the last call to `update_grid`
+}
+
+impl SimulationControl {
+    pub const MIN_SPEED: f32 = 0.125;
+    pub const MAX_SPEED: f32 = 8.0;
+
+    /// A running simulation at normal speed
+    pub fn new(ticks_per_second: f32) -> Self {
+        Self { paused: false, ticks_per_second, speed: 1.0, step_requested: false, accumulator: 0.0, ticks_run: 0 }
+    }
+
+    /// Pauses the simulation and advances it by a single tick
+    pub fn step(&mut self) {
+        self.paused = true;
+        self.step_requested = true;
+    }
+
+    /// How many ticks the simulation advanced by this frame, once `update_grid` has run
+    pub fn ticks_run(&self) -> u32 {
+        self.ticks_run
+    }
+
+    /// How many ticks to run for a frame that took `delta` seconds
+    fn ticks_this_frame(&mut self, delta: f32) -> u32 {
+        if self.paused {
+            self.accumulator = 0.0;
+            return std::mem::take(&mut self.step_requested) as u32;
+        }
+        let rate = self.ticks_per_second.clamp(MIN_TICKS_PER_SECOND, MAX_TICKS_PER_SECOND);
+        self.accumulator += delta * rate * self.speed.clamp(Self::MIN_SPEED, Self::MAX_SPEED);
+        let ticks = self.accumulator.floor();
+        self.accumulator -= ticks;
+
+        // Drop whatever can't be caught up on, rather than falling further behind every frame
+        if ticks > MAX_TICKS_PER_FRAME as f32 {
+            self.accumulator = 0.0;
+            return MAX_TICKS_PER_FRAME;
+        }
+        ticks as u32
+    }
+}
+
+/// Advances the simulation by as many ticks as the time since the last frame calls for.
+/// `scratch` keeps the buffer the next state is written into between ticks.
+pub fn update_grid(
+    mut grid: ResMut<Grid>,
+    mut scratch: Local<Option<Grid>>,
+    mut rng: ResMut<SimulationRng>,
+    parallel: Res<ParallelUpdate>,
+    mut control: ResMut<SimulationControl>,
+    mut tick_time: ResMut<TickTime>,
+    time: Res<Time>,
+) {
+    control.ticks_run = control.ticks_this_frame(time.delta_secs());
+    for _ in 0..control.ticks_run {
+        let start = Instant::now();
+        tick(&mut grid, &mut scratch, &mut rng, parallel.0);
+        tick_time.0 = start.elapsed();
+    }
+}
+
+/// Advances the simulation by one tick
+fn tick(grid: &mut Grid, scratch: &mut Option<Grid>, rng: &mut SimulationRng, parallel: bool) {
+    grid.begin_tick();
+    let new_grid = scratch.get_or_insert_with(|| grid.clone());
+    new_grid.copy_changes_from(grid);
+
+    if parallel {
+        update_parallel(new_grid, grid, rng);
+    } else {
+        update_serial(new_grid, grid, rng);
+    }
+
+    // Set off explosions queued by burnt-out explosives or the explosion tool.
+    // Parallel chunks queue them in whatever order they finish, so sort them first.
+    let mut explosions = new_grid.take_explosions();
+    explosions.sort_by_key(|explosion| (explosion.y, explosion.x));
+    for explosion in explosions {
+        explode(new_grid, &explosion, rng);
+    }
+
+    // Spread heat after movement so it follows the particles that carry it
+    conduct_heat(new_grid);
+
+    std::mem::swap(grid, new_grid);
+}
+
+/// Updates every awake particle in a single random order
+fn update_serial(new_grid: &mut Grid, old_grid: &Grid, rng: &mut SimulationRng) {
+    let mut particles: Vec<(usize, usize)> = Vec::new();
+    for cy in 0..old_grid.chunks_y() {
+        for cx in 0..old_grid.chunks_x() {
+            if old_grid.is_chunk_active(cx, cy) {
+                collect_particles(old_grid, cx, cy, &mut particles);
+            }
+        }
+    }
 
     // Randomize update order
-    particles.shuffle(&mut thread_rng());
+    particles.shuffle(rng);
 
     // Update each particle using old state for reads, new state for writes
+    let new_grid = new_grid.writer();
     for (x, y) in particles {
-        if let Some(material) = get_material_at(x, y, &grid) {
-            material.update(x, y, &mut new_grid, grid.as_ref());
+        let material = old_grid.get(x, y).material();
+        material.update(x, y, &new_grid, old_grid, rng);
+    }
+}
+
+/// Updates awake chunks in four checkerboard phases. Within a phase no two
+/// chunks touch, so particles crossing chunk borders never race each other.
+fn update_parallel(new_grid: &mut Grid, old_grid: &Grid, rng: &mut SimulationRng) {
+    // Alternate which phase goes first so no direction is favoured
+    let mut phases = [(0, 0), (1, 0), (0, 1), (1, 1)];
+    phases.shuffle(rng);
+
+    let (chunks_x, chunks_y) = (old_grid.chunks_x(), old_grid.chunks_y());
+    let shared = SharedGrid(new_grid.writer());
+    for (px, py) in phases {
+        // Each chunk rolls its own generator, forked in a fixed order so thread
+        // scheduling doesn't change the outcome
+        let chunks: Vec<(usize, usize, StdRng)> = (py..chunks_y)
+            .step_by(2)
+            .flat_map(|cy| (px..chunks_x).step_by(2).map(move |cx| (cx, cy)))
+            .filter(|&(cx, cy)| old_grid.is_chunk_active(cx, cy))
+            .map(|(cx, cy)| (cx, cy, rng.fork()))
+            .collect();
+
+        chunks.into_par_iter().for_each(|(cx, cy, mut rng)| {
+            let mut particles = Vec::new();
+            collect_particles(old_grid, cx, cy, &mut particles);
+            particles.shuffle(&mut rng);
+
+            for (x, y) in particles {
+                let material = old_grid.get(x, y).material();
+                material.update(x, y, shared.writer(), old_grid, &mut rng);
+            }
+        });
+    }
+}
+
+/// Collects the non-empty cells of the chunk at (cx, cy)
+fn collect_particles(grid: &Grid, cx: usize, cy: usize, particles: &mut Vec<(usize, usize)>) {
+    let (xs, ys) = grid.chunk_bounds(cx, cy);
+    for y in ys {
+        for x in xs.clone() {
+            if !grid.is_empty(x, y) {
+                particles.push((x, y));
+            }
         }
     }
+}
+
+/// Hands the grid being written to the tasks of the parallel phases
+struct SharedGrid<'a>(GridWriter<'a>);
 
-    *grid = new_grid;
+// SAFETY: the writer only ever touches single cells, never the buffers as a whole.
+// Chunks of one phase are a whole chunk apart and particles read and write at most
+// `MAX_REACH` cells away, so no two tasks touch the same cell, and phases run one
+// after another. The explosion queue and chunk flags shared between tasks are synchronized.
+unsafe impl Sync for SharedGrid<'_> {}
+
+impl<'a> SharedGrid<'a> {
+    fn writer(&self) -> &GridWriter<'a> {
+        &self.0
+    }
 }
 
-fn get_material_at(x: usize, y: usize, grid: &Grid) -> Option<Material> {
-    let material = grid.get(x, y);
-    if material.material_type == Material::Empty as u8 {
-        return None;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MaterialRegistry;
+
+    // Sand and water falling onto burning wood next to a pool of lava
+    fn scene() -> Grid {
+        let registry = MaterialRegistry::builtin();
+        let material = |name| registry.find(name).unwrap();
+        let mut grid = Grid::new(registry.clone(), 100, 80);
+        for x in 10..90 {
+            for y in 5..20 {
+                grid.spawn(x, y, if x < 50 { registry.sand() } else { material("Water") });
+            }
+            for y in 60..70 {
+                grid.spawn(x, y, if x < 60 { material("Wood") } else { material("Lava") });
+            }
+        }
+        for x in 20..40 {
+            grid.spawn(x, 59, registry.fire());
+        }
+        grid
+    }
+
+    fn run(parallel: bool) -> Grid {
+        let mut grid = scene();
+        let mut scratch = None;
+        let mut rng = SimulationRng::new(42);
+        for _ in 0..120 {
+            tick(&mut grid, &mut scratch, &mut rng, parallel);
+        }
+        grid
+    }
+
+    fn assert_same_run(parallel: bool) {
+        let (first, second) = (run(parallel), run(parallel));
+        assert!(first.particles == second.particles, "particles differ between runs");
+        assert!(first.temperatures == second.temperatures, "temperatures differ between runs");
+        assert!(first.particles != scene().particles, "nothing happened");
+    }
+
+    #[test]
+    fn serial_updates_are_deterministic() {
+        assert_same_run(false);
+    }
+
+    #[test]
+    fn parallel_updates_are_deterministic() {
+        assert_same_run(true);
     }
-    
-    Some(match material.material_type {
-        1 => Material::Sand,
-        2 => Material::Water,
-        3 => Material::Concrete,
-        4 => Material::Smoke,
-        _ => Material::Empty,
-    })
 }
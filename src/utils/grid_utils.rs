@@ -1,24 +1,37 @@
 use bevy::prelude::*;
-use crate::config::{self, CAMERA_OFFSET_X, CELL_SIZE, GRID_HEIGHT, GRID_WIDTH};
 use crate::grid::Grid;
-use crate::materials::Material;
 
-/// Converts window coordinates to grid coordinates
-/// Returns None if the coordinates are outside the grid bounds
-pub fn get_grid_pos(window: &Window, camera: &Camera, camera_transform: &GlobalTransform) -> Option<(usize, usize)> {
-    if let Some(cursor_pos) = window.cursor_position() {
-        if let Ok(world_pos) = camera.viewport_to_world_2d(camera_transform, cursor_pos) {
-            let adjusted_x = world_pos.x + (GRID_WIDTH as f32 * CELL_SIZE / 2.0);
-            let adjusted_y = world_pos.y + (GRID_HEIGHT as f32 * CELL_SIZE / 2.0);
-            let grid_x = ((adjusted_x + CAMERA_OFFSET_X) / CELL_SIZE) as usize;
-            let grid_y = (GRID_HEIGHT as f32 - adjusted_y / CELL_SIZE) as usize;
-            
-            if grid_x < GRID_WIDTH && grid_y < GRID_HEIGHT {
-                return Some((grid_x, grid_y));
-            }
-        }
+/// Converts a window position to world coordinates as seen through the camera,
+/// at any zoom and pan. Returns None outside the camera's viewport (e.g. over the sidebar)
+pub fn window_to_world(camera: &Camera, camera_transform: &GlobalTransform, window_pos: Vec2) -> Option<Vec2> {
+    let viewport = camera.logical_viewport_rect()?;
+    if !viewport.contains(window_pos) {
+        return None;
     }
-    None
+    camera.viewport_to_world_2d(camera_transform, window_pos - viewport.min).ok()
+}
+
+/// Converts the cursor position to grid coordinates, for cells drawn `cell_size` world units wide
+/// Returns None if the cursor is outside the grid bounds or the camera's viewport
+pub fn get_grid_pos(window: &Window, camera: &Camera, camera_transform: &GlobalTransform, grid: &Grid, cell_size: f32) -> Option<(usize, usize)> {
+    let world_pos = window_to_world(camera, camera_transform, window.cursor_position()?)?;
+    // The grid is centred on the world origin, with its first row at the top
+    let grid_x = (world_pos.x / cell_size + grid.width() as f32 / 2.0).floor();
+    let grid_y = (grid.height() as f32 / 2.0 - world_pos.y / cell_size).floor();
+
+    grid.in_bounds(grid_x as isize, grid_y as isize).then_some((grid_x as usize, grid_y as usize))
+}
+
+/// Converts grid coordinates to the window-space rectangle covered by that cell
+pub fn get_cell_rect(camera: &Camera, camera_transform: &GlobalTransform, grid: &Grid, cell_size: f32, x: usize, y: usize) -> Option<Rect> {
+    let viewport = camera.logical_viewport_rect()?;
+    let corner = |x: usize, y: usize| {
+        let world_x = (x as f32 - grid.width() as f32 / 2.0) * cell_size;
+        let world_y = (grid.height() as f32 / 2.0 - y as f32) * cell_size;
+        let viewport_pos = camera.world_to_viewport(camera_transform, Vec3::new(world_x, world_y, 0.0)).ok()?;
+        Some(viewport_pos + viewport.min)
+    };
+    Some(Rect::from_corners(corner(x, y)?, corner(x + 1, y + 1)?))
 }
 
 /// Finds the maximum distance a particle can move horizontally before hitting an obstacle
@@ -30,14 +43,14 @@ pub fn find_horizontal_space(grid: &Grid, x: usize, y: usize, max_distance: usiz
     // Check both directions simultaneously
     for dx in 1..=max_distance {
         let mut check_left = x >= dx;
-        let mut check_right = x + dx < config::GRID_WIDTH;
+        let mut check_right = x + dx < grid.width();
         
         if !check_left && !check_right {
             break;
         }
 
         // Check left if possible
-        if check_left && grid.get(x - dx, y).material_type == Material::Empty as u8 {
+        if check_left && grid.is_empty(x - dx, y) {
             left_x = x - dx;
         } else {
             // Stop checking left if blocked
@@ -45,7 +58,7 @@ pub fn find_horizontal_space(grid: &Grid, x: usize, y: usize, max_distance: usiz
         }
 
         // Check right if possible
-        if check_right && grid.get(x + dx, y).material_type == Material::Empty as u8 {
+        if check_right && grid.is_empty(x + dx, y) {
             right_x = x + dx;
         } else {
             // Stop checking right if blocked
@@ -60,24 +73,3 @@ pub fn find_horizontal_space(grid: &Grid, x: usize, y: usize, max_distance: usiz
     
     (left_x, right_x)
 }
-
-/// Finds the maximum distance a particle can move vertically before hitting an obstacle
-/// Returns the maximum possible y coordinate in downward direction
-pub fn find_vertical_space(grid: &Grid, x: usize, y: usize, max_distance: usize) -> usize {
-    // Early return if already at bottom or max_distance is 0
-    if y >= config::GRID_HEIGHT - 1 || max_distance == 0 {
-        return y;
-    }
-
-    // Calculate the maximum possible distance considering grid bounds
-    let max_check = (max_distance + y).min(config::GRID_HEIGHT - 1) - y;
-    
-    // Use iterator to find first non-empty cell
-    match (1..=max_check)
-        .take_while(|&dy| grid.get(x, y + dy).material_type == Material::Empty as u8)
-        .last()
-    {
-        Some(last_empty) => y + last_empty,
-        None => y
-    }
-}
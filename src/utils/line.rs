@@ -1,5 +1,5 @@
-use crate::config::{GRID_HEIGHT, GRID_WIDTH};
-
+/// Cells on the line from (x0, y0) towards (x1, y1), excluding the end itself.
+/// They all lie within the rectangle spanned by the two ends.
 pub(crate) fn bresenham_line(x0: usize, y0: usize, x1: usize, y1: usize) -> impl Iterator<Item = (usize, usize)> {
     let dx = (x1 as isize - x0 as isize).abs();
     let dy = (y1 as isize - y0 as isize).abs();
@@ -11,7 +11,7 @@ pub(crate) fn bresenham_line(x0: usize, y0: usize, x1: usize, y1: usize) -> impl
     let mut y = y0 as isize;
 
     std::iter::from_fn(move || {
-        if (x as usize == x1 && y as usize == y1) || x < 0 || y < 0 || x >= GRID_WIDTH as isize || y >= GRID_HEIGHT as isize {
+        if x as usize == x1 && y as usize == y1 {
             None
         } else {
             let current = (x as usize, y as usize);
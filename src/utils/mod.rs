@@ -1,2 +1,4 @@
 pub mod line;
+pub mod shapes;
 pub mod grid_utils;
+pub mod rng;
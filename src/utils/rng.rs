@@ -0,0 +1,58 @@
+use bevy::prelude::*;
+use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+/// The random number generator driving the simulation.
+/// Every random roll of a tick is drawn from it, so a seed plus the same
+/// input always reproduces the same grid.
+#[derive(Resource)]
+pub struct SimulationRng {
+    seed: u64,
+    rng: StdRng,
+}
+
+impl SimulationRng {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: StdRng::seed_from_u64(seed) }
+    }
+
+    /// Starts from a random seed, logged so the run can be reproduced
+    pub fn from_entropy() -> Self {
+        let seed = rand::random();
+        info!("Simulation seed: {seed}");
+        Self::new(seed)
+    }
+
+    /// The seed this generator started from
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Splits off an independent generator, e.g. for work done on another thread
+    pub fn fork(&mut self) -> StdRng {
+        StdRng::seed_from_u64(self.rng.next_u64())
+    }
+}
+
+impl RngCore for SimulationRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+}
+
+/// Well-mixed bits derived from a cell position and a salt (SplitMix64), for
+/// variation that has to stay the same without drawing from the simulation generator
+pub fn cell_noise(x: usize, y: usize, salt: u64) -> u64 {
+    let mut z = (x as u64) ^ (y as u64).rotate_left(32) ^ salt.wrapping_mul(0xD6E8_FEB8_6659_FD93);
+    z = z.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
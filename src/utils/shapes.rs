@@ -0,0 +1,117 @@
+use crate::grid::Grid;
+use crate::utils::line::bresenham_line;
+
+/// Cells on the straight line from `start` to `end`, both ends included
+pub fn line_cells(start: (usize, usize), end: (usize, usize)) -> impl Iterator<Item = (usize, usize)> {
+    bresenham_line(start.0, start.1, end.0, end.1).chain(std::iter::once(end))
+}
+
+/// Cells of the rectangle spanned by two opposite corners, either all of them or only its outline
+pub fn rectangle_cells(a: (usize, usize), b: (usize, usize), filled: bool) -> Vec<(usize, usize)> {
+    let (x0, x1) = (a.0.min(b.0), a.0.max(b.0));
+    let (y0, y1) = (a.1.min(b.1), a.1.max(b.1));
+
+    let mut cells = Vec::new();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            if filled || x == x0 || x == x1 || y == y0 || y == y1 {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// Cells of the ellipse inscribed in the rectangle spanned by two opposite corners,
+/// either all of them or only its outline
+pub fn ellipse_cells(a: (usize, usize), b: (usize, usize), filled: bool) -> Vec<(usize, usize)> {
+    let (x0, x1) = (a.0.min(b.0), a.0.max(b.0));
+    let (y0, y1) = (a.1.min(b.1), a.1.max(b.1));
+    let (center_x, center_y) = ((x0 + x1) as f32 / 2.0, (y0 + y1) as f32 / 2.0);
+    // Half a cell larger, so the ellipse reaches the cells on the rectangle's edges
+    let (radius_x, radius_y) = ((x1 - x0) as f32 / 2.0 + 0.5, (y1 - y0) as f32 / 2.0 + 0.5);
+
+    let inside = |x: isize, y: isize| {
+        let dx = (x as f32 - center_x) / radius_x;
+        let dy = (y as f32 - center_y) / radius_y;
+        dx * dx + dy * dy <= 1.0
+    };
+
+    let mut cells = Vec::new();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            let (ix, iy) = (x as isize, y as isize);
+            if !inside(ix, iy) {
+                continue;
+            }
+            // Outline cells are the ones with any of their eight neighbours outside the ellipse,
+            // which keeps the outline connected through cell sides so nothing leaks through it
+            let on_edge = (-1..=1).any(|dy| (-1..=1).any(|dx| !inside(ix + dx, iy + dy)));
+            if filled || on_edge {
+                cells.push((x, y));
+            }
+        }
+    }
+    cells
+}
+
+/// The contiguous region of cells holding the same material as (x, y), connected through their sides
+pub fn flood_fill_cells(grid: &Grid, x: usize, y: usize) -> Vec<(usize, usize)> {
+    let material = grid.get(x, y).material();
+    let mut visited = vec![false; grid.width() * grid.height()];
+    let mut stack = vec![(x, y)];
+    let mut cells = Vec::new();
+    visited[y * grid.width() + x] = true;
+
+    while let Some((x, y)) = stack.pop() {
+        cells.push((x, y));
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let (nx, ny) = (x as isize + dx, y as isize + dy);
+            if !grid.in_bounds(nx, ny) {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            let idx = ny * grid.width() + nx;
+            if !visited[idx] && grid.get(nx, ny).material() == material {
+                visited[idx] = true;
+                stack.push((nx, ny));
+            }
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::MaterialRegistry;
+
+    // A 10x10 grid cut in two by a diagonal line of sand from (0, 9) to (9, 0)
+    fn diagonal_grid() -> Grid {
+        let registry = MaterialRegistry::builtin();
+        let sand = registry.sand();
+        let mut grid = Grid::new(registry, 10, 10);
+        for x in 0..10 {
+            grid.set(x, 9 - x, sand);
+        }
+        grid
+    }
+
+    #[test]
+    fn flood_fill_stays_on_its_side_of_a_diagonal() {
+        let grid = diagonal_grid();
+        let mut cells = flood_fill_cells(&grid, 0, 0);
+        cells.sort_unstable();
+        let mut above: Vec<_> = (0..10).flat_map(|y| (0..10).map(move |x| (x, y))).filter(|(x, y)| x + y < 9).collect();
+        above.sort_unstable();
+        assert_eq!(cells, above);
+        assert_eq!(flood_fill_cells(&grid, 9, 9).len(), above.len());
+    }
+
+    #[test]
+    fn flood_fill_connects_through_sides_only() {
+        let grid = diagonal_grid();
+        // Each grain of the diagonal only touches the others at a corner
+        assert_eq!(flood_fill_cells(&grid, 3, 6), [(3, 6)]);
+    }
+}